@@ -0,0 +1,138 @@
+// Works out which artist a `/top-tracks?artist=` value means. It can be a
+// Spotify ID, an open.spotify.com share link or `spotify:artist:` URI, or a
+// name, which is looked up with Spotify's search.
+
+use crate::http;
+use crate::model::Artist;
+use reqwest::header::AUTHORIZATION;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::error::Error;
+
+// How many search results to pick the best match from
+const SEARCH_LIMIT: &str = "10";
+// How many near misses to suggest when nothing matches
+const MAX_SUGGESTIONS: usize = 5;
+
+pub enum Resolved {
+    Found(Artist),
+    // Nothing matched; holds the names of the closest search results, most
+    // popular first (empty when the search found nothing at all)
+    NotFound(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    artists: ArtistPage,
+}
+
+#[derive(Deserialize)]
+struct ArtistPage {
+    items: Vec<Artist>,
+}
+
+pub async fn resolve(api_url: &str, access_token: &str, input: &str) -> Result<Resolved, Box<dyn Error>> {
+    let input = input.trim();
+    match parse_id(input) {
+        Some(id) => get_artist(api_url, access_token, id).await,
+        None => {
+            let candidates = search_artists(api_url, access_token, input).await?;
+            Ok(best_match(input, candidates))
+        }
+    }
+}
+
+// The artist ID in a share link (`https://open.spotify.com/artist/<id>?si=...`,
+// possibly with a locale like `/intl-de/`), a `spotify:artist:<id>` URI or a
+// bare ID. Anything else is taken to be a name.
+fn parse_id(input: &str) -> Option<&str> {
+    let id = if let Some(rest) = input.strip_prefix("spotify:artist:") {
+        rest
+    } else if let Some(path) = input.strip_prefix("https://open.spotify.com/").or_else(|| input.strip_prefix("open.spotify.com/")) {
+        let mut segments = path.split(['/', '?', '#']);
+        let mut kind = segments.next()?;
+        if kind.starts_with("intl-") {
+            kind = segments.next()?;
+        }
+        if kind != "artist" {
+            return None;
+        }
+        segments.next()?
+    } else {
+        input
+    };
+    is_spotify_id(id).then_some(id)
+}
+
+// Spotify IDs are 22 random base-62 characters. A one-word name can be 22
+// letters long too, but it won't have digits or capitals after the first letter.
+fn is_spotify_id(s: &str) -> bool {
+    s.len() == 22
+        && s.chars().all(|c| c.is_ascii_alphanumeric())
+        && s.chars().skip(1).any(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+}
+
+async fn get_artist(api_url: &str, access_token: &str, id: &str) -> Result<Resolved, Box<dyn Error>> {
+    let request = http::client()
+        .get(format!("{}/v1/artists/{}", api_url, id))
+        .header(AUTHORIZATION, format!("Bearer {}", access_token));
+    let response = http::send(request).await?;
+    // Spotify answers 400 rather than 404 for some well-formed IDs that don't exist
+    if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST) {
+        return Ok(Resolved::NotFound(Vec::new()));
+    }
+    let artist: Artist = response.error_for_status()?.json().await?;
+    Ok(Resolved::Found(artist))
+}
+
+async fn search_artists(api_url: &str, access_token: &str, name: &str) -> Result<Vec<Artist>, Box<dyn Error>> {
+    let request = http::client()
+        .get(format!("{}/v1/search", api_url))
+        .header(AUTHORIZATION, format!("Bearer {}", access_token))
+        .query(&[("q", name), ("type", "artist"), ("limit", SEARCH_LIMIT)]);
+    let response = http::send(request).await?.error_for_status()?;
+
+    let search: SearchResponse = response.json().await?;
+    Ok(search.artists.items)
+}
+
+// Search results whose name is the one asked for win, then those whose name
+// contains it (or is contained in it, for "the beatles band"); the most
+// popular of the winning group is the match. If no result's name overlaps
+// with the input, it's a miss and the results are only suggestions.
+fn best_match(input: &str, mut candidates: Vec<Artist>) -> Resolved {
+    let wanted = normalize(input);
+    // Input that is all punctuation can't be compared with anything
+    if wanted.is_empty() {
+        return Resolved::NotFound(candidates.into_iter().take(MAX_SUGGESTIONS).map(|artist| artist.name).collect());
+    }
+    let closeness = |artist: &Artist| {
+        let name = normalize(&artist.name);
+        if name.is_empty() {
+            0
+        } else if name == wanted {
+            2
+        } else if name.contains(&wanted) || wanted.contains(&name) {
+            1
+        } else {
+            0
+        }
+    };
+
+    candidates.sort_by_key(|artist| std::cmp::Reverse((closeness(artist), artist.popularity.unwrap_or(0))));
+    match candidates.first() {
+        Some(best) if closeness(best) > 0 => Resolved::Found(candidates.swap_remove(0)),
+        _ => Resolved::NotFound(candidates.into_iter().take(MAX_SUGGESTIONS).map(|artist| artist.name).collect()),
+    }
+}
+
+// Case-insensitive, ignoring punctuation, runs of whitespace and a leading
+// "The", so "AC/DC" matches "acdc" and "beatles" matches "The Beatles"
+fn normalize(name: &str) -> String {
+    let kept: String = name.chars().filter(|c| c.is_alphanumeric() || c.is_whitespace()).collect();
+    let normalized = kept.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    match normalized.strip_prefix("the ") {
+        Some(rest) => rest.to_string(),
+        None => normalized,
+    }
+}
@@ -0,0 +1,172 @@
+// Sends every request to Spotify through one shared client, retrying when
+// Spotify rate limits us (429 + Retry-After) or fails transiently (5xx,
+// connection errors) with jittered exponential backoff, up to a total time cap.
+
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// Backoff before the first retry, doubled for each one after that up to MAX_DELAY
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+// Don't start another attempt later than this after the first
+const MAX_RETRY_TIME: Duration = Duration::from_secs(30);
+
+pub fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(Client::new)
+}
+
+// Sends the request, retrying 429s and transient failures. Once the retry time
+// is used up the last response or error is returned as is. A POST may already
+// have taken effect when it gets a 5xx or times out, so only idempotent
+// requests are retried then.
+pub async fn send(request: RequestBuilder) -> Result<Response, reqwest::Error> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let idempotent = is_idempotent(request.method());
+    let start = Instant::now();
+    let mut attempt = 0;
+    loop {
+        // Requests with a streaming body can't be cloned, so they only get one try
+        let Some(this_attempt) = request.try_clone() else {
+            return client.execute(request).await;
+        };
+        let result = client.execute(this_attempt).await;
+
+        let delay = match &result {
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => retry_after(response).unwrap_or_else(|| backoff(attempt)),
+            Ok(response) if idempotent && is_transient(response.status()) => backoff(attempt),
+            // A connection error means the request never went out
+            Err(e) if e.is_connect() || (idempotent && e.is_timeout()) => backoff(attempt),
+            _ => return result,
+        };
+        if start.elapsed() + delay > MAX_RETRY_TIME {
+            return result;
+        }
+
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+// Spotify sends Retry-After as whole seconds
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    Some(Duration::from_secs(seconds.trim().parse().ok()?))
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::INTERNAL_SERVER_ERROR | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+// GET, PUT and DELETE can safely be sent twice
+fn is_idempotent(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::PUT | Method::DELETE)
+}
+
+// Somewhere between half and all of the exponential delay, so callers that
+// failed together don't all retry at the same moment
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_DELAY);
+    let half = ceiling / 2;
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    // A local server that answers one request per connection with each of
+    // `responses` in turn (a status line, optionally followed by header
+    // lines). Returns its URL and a count of the requests it got.
+    async fn stub(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                read_request(&mut stream).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let reply = format!(
+                    "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    response
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    // Reads a request's head and body, so the reply isn't sent early
+    async fn read_request(stream: &mut TcpStream) {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        loop {
+            let read = stream.read(&mut buf).await.unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf[..read]);
+            let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+            let body_len = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|len| len.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if request.len() >= end + 4 + body_len {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_a_429_after_retry_after() {
+        let (url, requests) = stub(vec!["429 Too Many Requests\r\nretry-after: 1", "200 OK"]).await;
+
+        let start = Instant::now();
+        let response = send(Client::new().get(&url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_a_post_after_a_5xx() {
+        let (url, requests) = stub(vec!["503 Service Unavailable", "200 OK"]).await;
+
+        let request = Client::new().post(&url).body(r#"{"name":"Top tracks"}"#);
+        let response = send(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_the_wait_would_pass_the_retry_time() {
+        let (url, requests) =
+            stub(vec!["429 Too Many Requests\r\nretry-after: 60", "200 OK"]).await;
+
+        let start = Instant::now();
+        let response = send(Client::new().get(&url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < MAX_RETRY_TIME);
+    }
+}
@@ -1,27 +1,64 @@
+mod artist;
+mod http;
+mod model;
+
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use artist::Resolved;
 use dotenv::dotenv;
+use model::{Artist, Track};
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
 use std::env;
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const DEFAULT_ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+const DEFAULT_API_URL: &str = "https://api.spotify.com";
+
+// Refresh the app token this long before Spotify says it expires
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
 
-#[derive(Clone)]
 struct AppState {
     client_id: String,
     client_secret: String,
+    accounts_url: String,
+    api_url: String,
+    // Shared by all workers; locked across the fetch so only one request refreshes it
+    token: Mutex<Option<CachedToken>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct AuthResponse {
+struct CachedToken {
     access_token: String,
+    expires_at: Instant,
+}
+
+impl AppState {
+    // Returns the cached app token, fetching a new one first if there is none
+    // yet or it is about to expire
+    async fn access_token(&self) -> Result<String, Box<dyn Error>> {
+        let mut token = self.token.lock().await;
+        if let Some(cached) = token.as_ref() {
+            if Instant::now() + TOKEN_EXPIRY_MARGIN < cached.expires_at {
+                return Ok(cached.access_token.clone());
+            }
+        }
+
+        let auth_response = get_access_token(&self.accounts_url, &self.client_id, &self.client_secret).await?;
+        let access_token = auth_response.access_token.clone();
+        *token = Some(CachedToken {
+            access_token: auth_response.access_token,
+            expires_at: Instant::now() + Duration::from_secs(auth_response.expires_in),
+        });
+        Ok(access_token)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct Track {
-    name: String,
-    popularity: u32,
+struct AuthResponse {
+    access_token: String,
+    expires_in: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -29,55 +66,86 @@ struct TopTracksResponse {
     tracks: Vec<Track>,
 }
 
-async fn get_access_token(client_id: &str, client_secret: &str) -> Result<String, Box<dyn Error>> {
-    let client = reqwest::Client::new();
+#[derive(Deserialize)]
+struct TopTracksQuery {
+    // A name, Spotify ID, open.spotify.com link or spotify:artist: URI
+    artist: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ArtistTopTracks {
+    artist: Artist,
+    tracks: Vec<Track>,
+}
+
+#[derive(Serialize)]
+struct ArtistNotFound {
+    error: String,
+    suggestions: Vec<String>,
+}
+
+async fn get_access_token(accounts_url: &str, client_id: &str, client_secret: &str) -> Result<AuthResponse, Box<dyn Error>> {
+    let client = http::client();
     let params = [
         ("grant_type", "client_credentials"),
         ("client_id", client_id),
         ("client_secret", client_secret),
     ];
 
-    let response = client
-        .post("https://accounts.spotify.com/api/token")
-        .form(&params)
-        .send()
-        .await?;
+    let request = client
+        .post(format!("{}/api/token", accounts_url))
+        .form(&params);
+    let response = http::send(request).await?.error_for_status()?;
 
     let auth_response: AuthResponse = response.json().await?;
 
-    Ok(auth_response.access_token)
+    Ok(auth_response)
 }
 
-async fn get_artist_top_tracks(access_token: &str, artist_id: &str) -> Result<TopTracksResponse, Box<dyn Error>> {
-    let client = reqwest::Client::new();
-    let url = format!("https://api.spotify.com/v1/artists/{}/top-tracks?market=US", artist_id);
+async fn get_artist_top_tracks(api_url: &str, access_token: &str, artist_id: &str) -> Result<TopTracksResponse, Box<dyn Error>> {
+    let client = http::client();
+    let url = format!("{}/v1/artists/{}/top-tracks?market=US", api_url, artist_id);
 
-    let response = client
+    let request = client
         .get(&url)
         .header(AUTHORIZATION, format!("Bearer {}", access_token))
-        .header(CONTENT_TYPE, "application/json")
-        .send()
-        .await?;
+        .header(CONTENT_TYPE, "application/json");
+    let response = http::send(request).await?.error_for_status()?;
 
     let top_tracks: TopTracksResponse = response.json().await?;
     Ok(top_tracks)
 }
 
-async fn top_tracks_handler(state: web::Data<Arc<AppState>>) -> impl Responder {
-    let access_token = match get_access_token(&state.client_id, &state.client_secret).await {
+async fn top_tracks_handler(state: web::Data<Arc<AppState>>, query: web::Query<TopTracksQuery>) -> impl Responder {
+    let Some(input) = query.artist.as_deref().filter(|artist| !artist.trim().is_empty()) else {
+        return HttpResponse::BadRequest().body("Pick an artist with ?artist=<name, Spotify ID or open.spotify.com link>");
+    };
+
+    let access_token = match state.access_token().await {
         Ok(token) => token,
         Err(e) => return HttpResponse::InternalServerError().body(format!("Failed to get access token: {}", e)),
     };
 
-    // Example artist ID for Radiohead
-    let artist_id = "4Z8W4fKeB5YxbusRsdQVPb";
+    let artist = match artist::resolve(&state.api_url, &access_token, input).await {
+        Ok(Resolved::Found(artist)) => artist,
+        Ok(Resolved::NotFound(suggestions)) => {
+            return HttpResponse::NotFound().json(ArtistNotFound { error: format!("No artist matches {:?}", input.trim()), suggestions })
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Failed to look up the artist: {}", e)),
+    };
 
-    match get_artist_top_tracks(&access_token, artist_id).await {
-        Ok(top_tracks) => HttpResponse::Ok().json(top_tracks),
+    match get_artist_top_tracks(&state.api_url, &access_token, &artist.id).await {
+        Ok(top_tracks) => HttpResponse::Ok().json(ArtistTopTracks { artist, tracks: top_tracks.tracks }),
         Err(e) => HttpResponse::InternalServerError().body(format!("Failed to get top tracks: {}", e)),
     }
 }
 
+fn base_url_from_env(var: &str, default: &str) -> String {
+    env::var(var)
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| default.to_string())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
@@ -85,7 +153,11 @@ async fn main() -> std::io::Result<()> {
     let client_id = env::var("SPOTIFY_CLIENT_ID").expect("SPOTIFY_CLIENT_ID must be set");
     let client_secret = env::var("SPOTIFY_CLIENT_SECRET").expect("SPOTIFY_CLIENT_SECRET must be set");
 
-    let app_state = Arc::new(AppState { client_id, client_secret });
+    // Overridable so the server can run against a local mock of the Spotify APIs
+    let accounts_url = base_url_from_env("SPOTIFY_ACCOUNTS_URL", DEFAULT_ACCOUNTS_URL);
+    let api_url = base_url_from_env("SPOTIFY_API_URL", DEFAULT_API_URL);
+
+    let app_state = Arc::new(AppState { client_id, client_secret, accounts_url, api_url, token: Mutex::new(None) });
 
     HttpServer::new(move || {
         App::new()
@@ -0,0 +1,97 @@
+// The Spotify catalog objects the server reads and passes on: tracks, their
+// albums and artists, and the images and links that hang off them.
+//
+// Spotify leaves fields out or sends `null` depending on the endpoint (no
+// album when listing an album's tracks, no ids or popularity for local files),
+// so everything that can be missing falls back to empty/zero/None instead of
+// failing the whole response.
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Track {
+    // None for local files, which aren't in Spotify's catalog
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub uri: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub artists: Vec<Artist>,
+    #[serde(default)]
+    pub album: Option<Album>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub duration_ms: u64,
+    // 0 to 100, based on how much the track has been played recently
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub popularity: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub explicit: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub external_ids: ExternalIds,
+    #[serde(default)]
+    pub preview_url: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub external_urls: ExternalUrls,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub disc_number: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub track_number: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub is_local: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Album {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub name: String,
+    // "1997", "1997-05" or "1997-05-21", see release_date_precision
+    #[serde(default)]
+    pub release_date: Option<String>,
+    // "year", "month" or "day"
+    #[serde(default)]
+    pub release_date_precision: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub images: Vec<Image>,
+}
+
+// Only the full artist object (from search or /v1/artists/<id>) has a
+// popularity; the simplified one nested inside tracks and albums doesn't
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Artist {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub popularity: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Image {
+    pub url: String,
+    pub height: Option<u32>,
+    pub width: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExternalIds {
+    // International Standard Recording Code
+    pub isrc: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExternalUrls {
+    // Empty for local files
+    #[serde(default)]
+    pub spotify: String,
+}
+
+// Deserializes null the same as a missing field
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
@@ -0,0 +1,228 @@
+//! Works out which artist a `/top-tracks?artist=` value means. It can be a
+//! Spotify ID, an open.spotify.com share link or `spotify:artist:` URI, or a
+//! name, which is looked up with Spotify's search.
+
+use crate::http;
+use crate::model::Artist;
+use reqwest::header::AUTHORIZATION;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::error::Error;
+
+/// How many search results to pick the best match from
+const SEARCH_LIMIT: &str = "10";
+
+/// How many near misses to suggest when nothing matches
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Enum for the outcome of looking up an artist
+///
+/// # Arguments
+/// * `Found` - The artist that was asked for
+/// * `NotFound` - The names of the closest search results, most popular
+///   first (empty when the search found nothing at all)
+pub enum Resolved {
+    Found(Artist),
+    NotFound(Vec<String>),
+}
+
+/// Struct for the search response from spotify
+///
+/// # Arguments
+/// * `artists` - The page of artists that matched
+#[derive(Deserialize)]
+struct SearchResponse {
+    artists: ArtistPage,
+}
+
+/// Struct for a page of artists
+///
+/// # Arguments
+/// * `items` - A vector of artists
+#[derive(Deserialize)]
+struct ArtistPage {
+    items: Vec<Artist>,
+}
+
+/// Function to find the artist a user asked for
+///
+/// # Arguments
+/// * `api_url` - A string that holds the base URL of the Web API
+/// * `access_token` - A string that holds the access token
+/// * `input` - A string that holds an artist name, ID, share link or URI
+pub async fn resolve(
+    api_url: &str,
+    access_token: &str,
+    input: &str,
+) -> Result<Resolved, Box<dyn Error>> {
+    let input = input.trim();
+    match parse_id(input) {
+        Some(id) => get_artist(api_url, access_token, id).await,
+        None => {
+            let candidates = search_artists(api_url, access_token, input).await?;
+            Ok(best_match(input, candidates))
+        }
+    }
+}
+
+/// Function to get the artist ID out of a share link
+/// (`https://open.spotify.com/artist/<id>?si=...`, possibly with a locale like
+/// `/intl-de/`), a `spotify:artist:<id>` URI or a bare ID. Returns `None` for
+/// anything else, which is taken to be a name
+///
+/// # Arguments
+/// * `input` - A string that holds what the user asked for
+fn parse_id(input: &str) -> Option<&str> {
+    let id = if let Some(rest) = input.strip_prefix("spotify:artist:") {
+        rest
+    } else if let Some(path) = input
+        .strip_prefix("https://open.spotify.com/")
+        .or_else(|| input.strip_prefix("open.spotify.com/"))
+    {
+        let mut segments = path.split(['/', '?', '#']);
+        let mut kind = segments.next()?;
+        if kind.starts_with("intl-") {
+            kind = segments.next()?;
+        }
+        if kind != "artist" {
+            return None;
+        }
+        segments.next()?
+    } else {
+        input
+    };
+    is_spotify_id(id).then_some(id)
+}
+
+/// Function to check whether a string looks like a Spotify ID: 22 random
+/// base-62 characters. A one-word name can be 22 letters long too, but it
+/// won't have digits or capitals after the first letter
+///
+/// # Arguments
+/// * `s` - A string that might be an ID
+fn is_spotify_id(s: &str) -> bool {
+    s.len() == 22
+        && s.chars().all(|c| c.is_ascii_alphanumeric())
+        && s.chars()
+            .skip(1)
+            .any(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+}
+
+/// Function to get an artist by ID
+///
+/// # Arguments
+/// * `api_url` - A string that holds the base URL of the Web API
+/// * `access_token` - A string that holds the access token
+/// * `id` - A string that holds the artist id
+async fn get_artist(
+    api_url: &str,
+    access_token: &str,
+    id: &str,
+) -> Result<Resolved, Box<dyn Error>> {
+    let request = http::client()
+        .get(format!("{}/v1/artists/{}", api_url, id))
+        .header(AUTHORIZATION, format!("Bearer {}", access_token));
+    let response = http::send(request).await?;
+
+    // Spotify answers 400 rather than 404 for some well-formed IDs that don't exist
+    if matches!(
+        response.status(),
+        StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST
+    ) {
+        return Ok(Resolved::NotFound(Vec::new()));
+    }
+    let artist: Artist = response.error_for_status()?.json().await?;
+    Ok(Resolved::Found(artist))
+}
+
+/// Function to search for artists by name
+///
+/// # Arguments
+/// * `api_url` - A string that holds the base URL of the Web API
+/// * `access_token` - A string that holds the access token
+/// * `name` - A string that holds the name to search for
+async fn search_artists(
+    api_url: &str,
+    access_token: &str,
+    name: &str,
+) -> Result<Vec<Artist>, Box<dyn Error>> {
+    let request = http::client()
+        .get(format!("{}/v1/search", api_url))
+        .header(AUTHORIZATION, format!("Bearer {}", access_token))
+        .query(&[("q", name), ("type", "artist"), ("limit", SEARCH_LIMIT)]);
+    let response = http::send(request).await?.error_for_status()?;
+
+    let search: SearchResponse = response.json().await?;
+    Ok(search.artists.items)
+}
+
+/// Function to pick the artist a name means from the search results.
+/// Results whose name is the one asked for win, then those whose name
+/// contains it (or is contained in it); the most popular of the winning
+/// group is the match. If no result's name overlaps with the input, it's a
+/// miss and the results are only suggestions
+///
+/// # Arguments
+/// * `input` - A string that holds the name the user asked for
+/// * `candidates` - A vector of artists that the search returned
+fn best_match(input: &str, mut candidates: Vec<Artist>) -> Resolved {
+    let wanted = normalize(input);
+    // Input that is all punctuation can't be compared with anything
+    if wanted.is_empty() {
+        return Resolved::NotFound(
+            candidates
+                .into_iter()
+                .take(MAX_SUGGESTIONS)
+                .map(|artist| artist.name)
+                .collect(),
+        );
+    }
+    let closeness = |artist: &Artist| {
+        let name = normalize(&artist.name);
+        if name.is_empty() {
+            0
+        } else if name == wanted {
+            2
+        } else if name.contains(&wanted) || wanted.contains(&name) {
+            1
+        } else {
+            0
+        }
+    };
+
+    candidates.sort_by_key(|artist| {
+        std::cmp::Reverse((closeness(artist), artist.popularity.unwrap_or(0)))
+    });
+    match candidates.first() {
+        Some(best) if closeness(best) > 0 => Resolved::Found(candidates.swap_remove(0)),
+        _ => Resolved::NotFound(
+            candidates
+                .into_iter()
+                .take(MAX_SUGGESTIONS)
+                .map(|artist| artist.name)
+                .collect(),
+        ),
+    }
+}
+
+/// Function to make names comparable: case-insensitive, ignoring
+/// punctuation, runs of whitespace and a leading "The", so "AC/DC" matches
+/// "acdc" and "beatles" matches "The Beatles"
+///
+/// # Arguments
+/// * `name` - A string that holds an artist name
+fn normalize(name: &str) -> String {
+    let kept: String = name
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect();
+    let normalized = kept
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    match normalized.strip_prefix("the ") {
+        Some(rest) => rest.to_string(),
+        None => normalized,
+    }
+}
@@ -1,4 +1,10 @@
+mod artist;
+mod http;
+mod model;
+
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
+use artist::Resolved;
+use model::{Artist, Track};
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
 use std::env;
@@ -13,26 +19,46 @@ struct AuthResponse {
     access_token: String,
 }
 
-/// Struct for the track
+/// Struct for the top tracks response
 /// 
 /// # Arguments
-/// * `name` - A string that holds the name of the track
-/// * `popularity` - An unsigned 32-bit integer that holds the popularity of the track
+/// * `tracks` - A vector of tracks
 #[derive(Serialize, Deserialize, Debug)]
-struct Track {
-    name: String,
-    popularity: u32,
+struct TopTracksResponse {
+    tracks: Vec<Track>,
 }
 
-/// Struct for the top tracks response
-/// 
+/// Struct for the query string of the top tracks endpoint
+///
 /// # Arguments
+/// * `artist` - A name, Spotify ID, open.spotify.com link or `spotify:artist:` URI
+#[derive(Deserialize)]
+struct TopTracksQuery {
+    artist: Option<String>,
+}
+
+/// Struct for what the top tracks endpoint returns
+///
+/// # Arguments
+/// * `artist` - The artist the tracks are by
 /// * `tracks` - A vector of tracks
-#[derive(Serialize, Deserialize, Debug)]
-struct TopTracksResponse {
+#[derive(Serialize)]
+struct ArtistTopTracks {
+    artist: Artist,
     tracks: Vec<Track>,
 }
 
+/// Struct for the 404 returned when no artist matches
+///
+/// # Arguments
+/// * `error` - A string that says what wasn't found
+/// * `suggestions` - A vector of the names of similar artists
+#[derive(Serialize)]
+struct ArtistNotFound {
+    error: String,
+    suggestions: Vec<String>,
+}
+
 /// Function to greet a user. this is an API endpoint
 /// with GET request method as the decorator
 /// 
@@ -43,24 +69,46 @@ async fn greet(name: web::Path<String>) -> impl Responder {
     format!("Hello, {}!", name)
 }
 
+/// Default base URL of Spotify's accounts service
+const DEFAULT_ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+
+/// Default base URL of Spotify's Web API
+const DEFAULT_API_URL: &str = "https://api.spotify.com";
+
+/// Function to read a base URL from an environment variable, falling
+/// back to the given default (e.g. to point the app at a local mock server)
+/// 
+/// # Arguments
+/// * `var` - A string that holds the name of the environment variable
+/// * `default` - A string that holds the URL to use when the variable is not set
+fn base_url_from_env(var: &str, default: &str) -> String {
+    env::var(var)
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| default.to_string())
+}
+
 /// Function to get the access token
 /// 
 /// # Arguments
+/// * `accounts_url` - A string that holds the base URL of the accounts service
 /// * `client_id` - A string that holds the client id
 /// * `client_secret` - A string that holds the client secret
-async fn get_access_token(client_id: &str, client_secret: &str) -> Result<String, Box<dyn Error>> {
-    let client = reqwest::Client::new();
+async fn get_access_token(
+    accounts_url: &str,
+    client_id: &str,
+    client_secret: &str,
+) -> Result<String, Box<dyn Error>> {
+    let client = http::client();
     let params = [
         ("grant_type", "client_credentials"),
         ("client_id", client_id),
         ("client_secret", client_secret),
     ];
 
-    let response = client
-        .post("https://accounts.spotify.com/api/token")
-        .form(&params)
-        .send()
-        .await?;
+    let request = client
+        .post(format!("{}/api/token", accounts_url))
+        .form(&params);
+    let response = http::send(request).await?.error_for_status()?;
 
     let auth_response: AuthResponse = response.json().await?;
     Ok(auth_response.access_token)
@@ -71,24 +119,25 @@ async fn get_access_token(client_id: &str, client_secret: &str) -> Result<String
 /// the access token, and returns the top tracks of the artist
 /// 
 /// # Arguments
+/// * `api_url` - A string that holds the base URL of the Web API
 /// * `access_token` - A string that holds the access token
 /// * `artist_id` - A string that holds the artist id
 async fn get_artist_top_tracks(
+    api_url: &str,
     access_token: &str,
     artist_id: &str,
 ) -> Result<TopTracksResponse, Box<dyn Error>> {
-    let client = reqwest::Client::new();
+    let client = http::client();
     let url = format!(
-        "https://api.spotify.com/v1/artists/{}/top-tracks?market=US",
-        artist_id
+        "{}/v1/artists/{}/top-tracks?market=US",
+        api_url, artist_id
     );
 
-    let response = client
+    let request = client
         .get(&url)
         .header(AUTHORIZATION, format!("Bearer {}", access_token))
-        .header(CONTENT_TYPE, "application/json")
-        .send()
-        .await?;
+        .header(CONTENT_TYPE, "application/json");
+    let response = http::send(request).await?.error_for_status()?;
 
     let top_tracks: TopTracksResponse = response.json().await?;
     Ok(top_tracks)
@@ -99,15 +148,28 @@ async fn get_artist_top_tracks(
 /// *****************************************************
 /// #TODO: ADD this to an API endpoint
 /// *****************************************************
-async fn top_tracks_handler() -> impl Responder {
+///
+/// # Arguments
+/// * `query` - The query string, e.g. `?artist=radiohead`
+async fn top_tracks_handler(query: web::Query<TopTracksQuery>) -> impl Responder {
+    let Some(input) = query
+        .artist
+        .as_deref()
+        .filter(|artist| !artist.trim().is_empty())
+    else {
+        return HttpResponse::BadRequest()
+            .body("Pick an artist with ?artist=<name, Spotify ID or open.spotify.com link>");
+    };
 
     // Load the environment variables from the .env file
     let client_id: String = env::var("SPOTIFY_CLIENT_ID").expect("SPOTIFY_CLIENT_ID must be set");
     let client_secret: String =
         env::var("SPOTIFY_CLIENT_SECRET").expect("SPOTIFY_CLIENT_SECRET must be set");
+    let accounts_url = base_url_from_env("SPOTIFY_ACCOUNTS_URL", DEFAULT_ACCOUNTS_URL);
+    let api_url = base_url_from_env("SPOTIFY_API_URL", DEFAULT_API_URL);
 
     // fetch the access token from spotify's api    
-    let access_token = match get_access_token(&client_id, &client_secret).await {
+    let access_token = match get_access_token(&accounts_url, &client_id, &client_secret).await {
         Ok(token) => token,
         Err(e) => {
             return HttpResponse::InternalServerError()
@@ -115,12 +177,27 @@ async fn top_tracks_handler() -> impl Responder {
         }
     };
 
-    // Example artist ID for Radiohead
-    let artist_id = "4Z8W4fKeB5YxbusRsdQVPb";
+    // work out which artist was asked for, by name, ID or link
+    let artist = match artist::resolve(&api_url, &access_token, input).await {
+        Ok(Resolved::Found(artist)) => artist,
+        Ok(Resolved::NotFound(suggestions)) => {
+            return HttpResponse::NotFound().json(ArtistNotFound {
+                error: format!("No artist matches {:?}", input.trim()),
+                suggestions,
+            })
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to look up the artist: {}", e))
+        }
+    };
 
     // match a response or an error
-    match get_artist_top_tracks(&access_token, artist_id).await {
-        Ok(top_tracks) => HttpResponse::Ok().json(top_tracks),
+    match get_artist_top_tracks(&api_url, &access_token, &artist.id).await {
+        Ok(top_tracks) => HttpResponse::Ok().json(ArtistTopTracks {
+            artist,
+            tracks: top_tracks.tracks,
+        }),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Failed to get top tracks: {}", e))
         }
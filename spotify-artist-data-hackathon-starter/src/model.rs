@@ -0,0 +1,138 @@
+//! The Spotify catalog objects the server reads and passes on: tracks, their
+//! albums and artists, and the images and links that hang off them.
+//!
+//! Spotify leaves fields out or sends `null` depending on the endpoint, so
+//! everything that can be missing falls back to empty/zero/`None` instead of
+//! failing the whole response.
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Struct for the track
+///
+/// # Arguments
+/// * `id` - The track id, `None` for local files
+/// * `name` - A string that holds the name of the track
+/// * `uri` - The track's `spotify:track:<id>` URI
+/// * `artists` - The artists who performed the track
+/// * `album` - The album the track appears on, if Spotify included it
+/// * `duration_ms` - The length of the track in milliseconds
+/// * `popularity` - An unsigned 32-bit integer that holds the popularity of the track
+/// * `explicit` - Whether the track has explicit lyrics
+/// * `external_ids` - Identifiers outside Spotify, like the ISRC
+/// * `preview_url` - A 30 second preview clip, if there is one
+/// * `external_urls` - Links to the track on open.spotify.com
+/// * `disc_number` - Which disc of the album the track is on
+/// * `track_number` - The position of the track on its disc
+/// * `is_local` - Whether the track is a local file
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Track {
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub uri: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub artists: Vec<Artist>,
+    #[serde(default)]
+    pub album: Option<Album>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub duration_ms: u64,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub popularity: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub explicit: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub external_ids: ExternalIds,
+    #[serde(default)]
+    pub preview_url: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub external_urls: ExternalUrls,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub disc_number: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub track_number: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub is_local: bool,
+}
+
+/// Struct for the album a track appears on
+///
+/// # Arguments
+/// * `id` - The album id
+/// * `name` - A string that holds the name of the album
+/// * `release_date` - `1997`, `1997-05` or `1997-05-21`, see `release_date_precision`
+/// * `release_date_precision` - `year`, `month` or `day`
+/// * `images` - The cover art, widest first
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Album {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub name: String,
+    #[serde(default)]
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub release_date_precision: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub images: Vec<Image>,
+}
+
+/// Struct for an artist
+///
+/// # Arguments
+/// * `id` - The artist id
+/// * `name` - A string that holds the name of the artist
+/// * `popularity` - How popular the artist is, from 0 to 100. Only the full
+///   artist object (from search or `/v1/artists/<id>`) has it, not the one
+///   nested inside tracks and albums
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Artist {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub popularity: Option<u32>,
+}
+
+/// Struct for an image, like album cover art
+///
+/// # Arguments
+/// * `url` - Where the image can be downloaded from
+/// * `height` - The height in pixels, if known
+/// * `width` - The width in pixels, if known
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Image {
+    pub url: String,
+    pub height: Option<u32>,
+    pub width: Option<u32>,
+}
+
+/// Struct for the identifiers of a track outside Spotify
+///
+/// # Arguments
+/// * `isrc` - The International Standard Recording Code
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExternalIds {
+    pub isrc: Option<String>,
+}
+
+/// Struct for the links to an object on Spotify
+///
+/// # Arguments
+/// * `spotify` - The object's page on open.spotify.com, empty for local files
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExternalUrls {
+    #[serde(default)]
+    pub spotify: String,
+}
+
+/// Function to deserialize `null` the same as a missing field
+///
+/// # Arguments
+/// * `deserializer` - The deserializer holding the field's value
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
@@ -9,10 +9,12 @@
 //! - **Generic Programming**: Type-safe abstractions
 
 mod auth;
+mod model;
 
 use auth::get_auth_code;
 use dialoguer::Input;
 use dotenv::dotenv;
+use model::{Artist, Track};
 use openai::chat::{ChatCompletion, ChatCompletionMessage, ChatCompletionMessageRole};
 use openai::set_key;
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
@@ -26,29 +28,8 @@ use std::fmt;
 ///
 /// These structs represent the JSON responses from Spotify's Web API.
 /// They use `#[derive(Deserialize)]` to automatically parse JSON into Rust structs.
-
-/// Represents a single track from Spotify
-///
-/// **Rust Concept: Derive Macros**
-/// - `#[derive(Deserialize, Debug, Clone)]` automatically implements:
-///   - `Deserialize`: Converts JSON to this struct
-///   - `Debug`: Allows printing with `{:?}`
-///   - `Clone`: Allows creating copies of the struct
-#[derive(Deserialize, Debug, Clone)]
-struct Track {
-    name: String,
-    artists: Vec<Artist>,
-}
-
-/// Represents an artist from Spotify
-///
-/// **Rust Concept: Owned vs Borrowed Data**
-/// - `String` is an owned type (we own the memory)
-/// - `&str` would be a borrowed reference (we don't own it)
-#[derive(Deserialize, Debug, Clone)]
-struct Artist {
-    name: String,
-}
+/// The catalog objects themselves (`Track`, `Artist`, ...) are in `model`;
+/// these are the wrappers particular endpoints put around them.
 
 /// Represents the response from Spotify's top tracks endpoint
 ///
@@ -574,7 +555,9 @@ fn iterator_error_example() {
         name: "Song 1".to_string(),
         artists: vec![Artist {
             name: "Artist 1".to_string(),
+            ..Default::default()
         }],
+        ..Default::default()
     }];
 
     let iterator = tracks.into_iter(); // Moves ownership of tracks
@@ -0,0 +1,107 @@
+//! Audio features (danceability, energy, valence, tempo, key and mode) for any
+//! list of tracks, looked up in batches with a few requests in flight at once.
+
+use crate::auth::provider::{TokenProvider, TokenSource};
+use crate::paging;
+use crate::MusicAnalysisError;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// The most track ids Spotify accepts in one audio-features request.
+pub const MAX_IDS_PER_REQUEST: usize = 100;
+
+/// How many batch requests run at the same time.
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+
+const PITCH_CLASSES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AudioFeatures {
+    /// 0.0 to 1.0, how suitable the track is for dancing
+    pub danceability: f32,
+    /// 0.0 to 1.0, perceived intensity and activity
+    pub energy: f32,
+    /// 0.0 to 1.0, from sad or angry to happy or cheerful
+    pub valence: f32,
+    /// Beats per minute
+    pub tempo: f32,
+    /// Pitch class of the key (0 = C, 1 = C#, ...), -1 if none was detected
+    pub key: i32,
+    /// 1 for major, 0 for minor
+    pub mode: i32,
+}
+
+#[derive(Deserialize)]
+struct AudioFeaturesResponse {
+    /// `null` for tracks Spotify has no features for
+    audio_features: Vec<Option<AudioFeatures>>,
+}
+
+/// Looks up the audio features of each track id, in the same order as `ids`,
+/// with `None` where Spotify has none.
+pub async fn get_audio_features<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
+    ids: &[String],
+) -> Result<Vec<Option<AudioFeatures>>, MusicAnalysisError> {
+    let url = format!("{}/v1/audio-features", api_url);
+
+    // `buffered` runs up to MAX_CONCURRENT_REQUESTS batches at once but yields
+    // their results in order
+    let batches: Vec<AudioFeaturesResponse> = stream::iter(ids.chunks(MAX_IDS_PER_REQUEST))
+        .map(|batch| {
+            let url = &url;
+            async move {
+                let ids = batch.join(",");
+                paging::fetch_json(tokens, url, &[("ids", &ids)]).await
+            }
+        })
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .try_collect()
+        .await?;
+
+    Ok(batches
+        .into_iter()
+        .flat_map(|batch| batch.audio_features)
+        .collect())
+}
+
+/// A one-line summary: the average danceability, energy, valence and tempo,
+/// and the most common key. `None` if `features` is empty.
+pub fn summarize(features: &[AudioFeatures]) -> Option<String> {
+    if features.is_empty() {
+        return None;
+    }
+    let average = |value: fn(&AudioFeatures) -> f32| {
+        features.iter().map(value).sum::<f32>() / features.len() as f32
+    };
+
+    let mut summary = format!(
+        "average danceability {:.2}, energy {:.2}, valence {:.2}, tempo {:.0} BPM",
+        average(|f| f.danceability),
+        average(|f| f.energy),
+        average(|f| f.valence),
+        average(|f| f.tempo)
+    );
+
+    let mut keys: HashMap<(i32, i32), usize> = HashMap::new();
+    for f in features.iter().filter(|f| f.key >= 0) {
+        *keys.entry((f.key, f.mode)).or_insert(0) += 1;
+    }
+    // Ties go to the lowest key so the summary doesn't change between runs
+    let most_common = keys
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)));
+    if let Some(((key, mode), _)) = most_common {
+        let mode = if mode == 1 { "major" } else { "minor" };
+        summary.push_str(&format!(
+            "; most common key {} {}",
+            PITCH_CLASSES[key as usize % 12],
+            mode
+        ));
+    }
+    Some(summary)
+}
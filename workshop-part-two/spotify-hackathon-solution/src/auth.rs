@@ -1,125 +1,580 @@
-use reqwest::Client;
+use crate::http;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use reqwest::{RequestBuilder, StatusCode};
+use scope::Scopes;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::error::Error;
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpListener;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use url::Url;
 use webbrowser;
 
+pub mod cache;
+pub mod provider;
+pub mod scope;
+
+pub const DEFAULT_ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+
+/// Tokens are treated as expired this many seconds early, so a request started
+/// just before expiry doesn't fail on its way to Spotify.
+const EXPIRY_MARGIN_SECS: u64 = 60;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AuthResponse {
     pub access_token: String,
     pub token_type: String,
     pub expires_in: u64,
+    // Refresh responses may omit this; `refresh_spotify_token` fills it back in
+    #[serde(default)]
     pub refresh_token: String,
+    /// Unix timestamp (seconds) at which `access_token` expires. Not sent by
+    /// Spotify; set from `expires_in` when the token is received.
+    #[serde(default)]
+    pub expires_at: u64,
+    /// The scopes this token was granted
+    #[serde(default)]
+    pub scope: Scopes,
+}
+
+impl AuthResponse {
+    pub fn is_expired(&self) -> bool {
+        unix_now() + EXPIRY_MARGIN_SECS >= self.expires_at
+    }
+
+    /// A refresh doesn't change what was granted, so when the refresh response
+    /// leaves out `scope`, keep the previous token's.
+    pub fn inherit_scope(&mut self, previous: &AuthResponse) {
+        if self.scope.is_empty() {
+            self.scope = previous.scope.clone();
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// The callback's `state` did not match the one sent with the authorize
+    /// request, so the code may not belong to this login.
+    StateMismatch,
+    /// The refresh token is invalid, expired or revoked.
+    InvalidGrant(String),
+    /// The authorization code was refused: already used, expired, or sent
+    /// with a redirect URI or PKCE verifier that doesn't match the login.
+    CodeRejected(String),
+    /// Spotify doesn't recognise the client id/secret.
+    InvalidClient(String),
+    /// The app isn't allowed to use this grant type.
+    UnsupportedGrantType(String),
+    /// Any other failure from the token endpoint, with Spotify's error text.
+    TokenRequest { status: StatusCode, message: String },
+    /// Spotify couldn't be reached, or the response couldn't be read.
+    Network(reqwest::Error),
+    /// The token endpoint answered 2xx, but not with the tokens we expect.
+    Decode(serde_json::Error),
+    /// The user declined the authorization request in the browser.
+    AccessDenied,
+    /// Spotify redirected back with an `error` other than `access_denied`.
+    Authorization(String),
+    /// No callback arrived before the login deadline.
+    Timeout(Duration),
+    /// The configured redirect URI can't be listened on.
+    InvalidRedirectUri(String),
+    /// A URL pasted in headless mode had no authorization code in it.
+    MissingCode,
+}
+
+impl AuthError {
+    /// Decodes the `{"error": ..., "error_description": ...}` body the token
+    /// endpoint sends with a failure status.
+    fn from_response(status: StatusCode, body: &str) -> Self {
+        let Ok(error) = serde_json::from_str::<OAuthErrorBody>(body) else {
+            return AuthError::TokenRequest {
+                status,
+                message: body.to_string(),
+            };
+        };
+        let description = error.error_description;
+        match error.error.as_str() {
+            "invalid_grant" => AuthError::InvalidGrant(description),
+            "invalid_client" => AuthError::InvalidClient(description),
+            "unsupported_grant_type" => AuthError::UnsupportedGrantType(description),
+            _ => AuthError::TokenRequest {
+                status,
+                message: format!("{}: {}", error.error, description),
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OAuthErrorBody {
+    error: String,
+    #[serde(default)]
+    error_description: String,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::StateMismatch => {
+                write!(
+                    f,
+                    "OAuth state mismatch; the callback was not for this login"
+                )
+            }
+            AuthError::InvalidGrant(description) => write!(f, "Invalid grant: {}", description),
+            AuthError::CodeRejected(description) => {
+                write!(f, "Authorization code rejected: {}", description)
+            }
+            AuthError::InvalidClient(description) => write!(f, "Invalid client: {}", description),
+            AuthError::UnsupportedGrantType(description) => {
+                write!(f, "Unsupported grant type: {}", description)
+            }
+            AuthError::TokenRequest { status, message } => {
+                write!(f, "Token request failed ({}): {}", status, message)
+            }
+            AuthError::Network(e) => write!(f, "Could not reach Spotify: {}", e),
+            AuthError::Decode(e) => write!(f, "Unexpected token response from Spotify: {}", e),
+            AuthError::AccessDenied => write!(f, "Access was denied in the browser"),
+            AuthError::Authorization(error) => write!(f, "Authorization failed: {}", error),
+            AuthError::Timeout(timeout) => write!(
+                f,
+                "No login callback received within {} seconds",
+                timeout.as_secs()
+            ),
+            AuthError::InvalidRedirectUri(uri) => {
+                write!(f, "Invalid redirect URI for local login: {}", uri)
+            }
+            AuthError::MissingCode => write!(f, "No authorization code found in the pasted URL"),
+        }
+    }
+}
+
+impl Error for AuthError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AuthError::Network(e) => Some(e),
+            AuthError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// How the authorization code gets back to us once the user approves the login
+#[derive(Clone, Copy, Debug)]
+pub enum LoginMode {
+    /// Open a browser and catch the redirect on the local redirect URI, giving
+    /// up after `timeout`.
+    Browser { timeout: Duration },
+    /// Print the authorize URL and read the redirected URL (or just the code)
+    /// from stdin, for sessions with no browser or loopback access.
+    Headless,
+}
+
+/// A PKCE code verifier and the S256 challenge derived from it.
+///
+/// The challenge is sent with the authorize request and the verifier with the
+/// token exchange, which lets a public client (no `client_secret`) prove that
+/// it is the same client that started the login.
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl Pkce {
+    pub fn generate() -> Self {
+        // RFC 7636 allows 43-128 characters from the unreserved set
+        let verifier = random_string(64);
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Pkce {
+            verifier,
+            challenge,
+        }
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
 }
 
 pub async fn get_spotify_token(
+    accounts_url: &str,
     client_id: &str,
     client_secret: &str,
     redirect_uri: &str,
     code: &str,
-) -> Result<AuthResponse, Box<dyn Error>> {
-    let client = Client::new();
+) -> Result<AuthResponse, AuthError> {
+    let client = http::client();
     let params = [
         ("grant_type", "authorization_code"),
         ("code", code),
         ("redirect_uri", redirect_uri),
     ];
 
-    let response = client
-        .post("https://accounts.spotify.com/api/token")
+    let request = client
+        .post(format!("{}/api/token", accounts_url))
         .basic_auth(client_id, Some(client_secret))
-        .form(&params)
-        .send()
-        .await?;
+        .form(&params);
+
+    request_token(request).await.map_err(code_rejected)
+}
 
-    if response.status().is_success() {
-        let auth_response: AuthResponse = response.json().await?;
+/// Exchanges an authorization code obtained with [`get_auth_code_pkce`] for
+/// tokens. No client secret is sent; the code verifier proves possession instead.
+pub async fn get_spotify_token_pkce(
+    accounts_url: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+) -> Result<AuthResponse, AuthError> {
+    let client = http::client();
+    let params = [
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", client_id),
+        ("code_verifier", code_verifier),
+    ];
+
+    let request = client
+        .post(format!("{}/api/token", accounts_url))
+        .form(&params);
+
+    request_token(request).await.map_err(code_rejected)
+}
+
+/// An `invalid_grant` from a code exchange is about the code, not a stale
+/// login, so it gets its own variant.
+fn code_rejected(err: AuthError) -> AuthError {
+    match err {
+        AuthError::InvalidGrant(description) => AuthError::CodeRejected(description),
+        err => err,
+    }
+}
+
+async fn request_token(request: RequestBuilder) -> Result<AuthResponse, AuthError> {
+    let response = http::send(request).await.map_err(AuthError::Network)?;
+    let status = response.status();
+    let body = response.text().await.map_err(AuthError::Network)?;
+
+    if status.is_success() {
+        let mut auth_response: AuthResponse =
+            serde_json::from_str(&body).map_err(AuthError::Decode)?;
+        auth_response.expires_at = unix_now() + auth_response.expires_in;
         Ok(auth_response)
     } else {
-        Err(format!("Error: {}", response.status()).into())
+        Err(AuthError::from_response(status, &body))
     }
 }
 
-pub async fn refresh_spotify_token(
+/// Gets an app-only token with the client-credentials grant. It can't access
+/// user data, and comes without a refresh token; just request a new one.
+pub async fn get_client_credentials_token(
+    accounts_url: &str,
     client_id: &str,
     client_secret: &str,
+) -> Result<AuthResponse, AuthError> {
+    let client = http::client();
+    let params = [("grant_type", "client_credentials")];
+
+    let request = client
+        .post(format!("{}/api/token", accounts_url))
+        .basic_auth(client_id, Some(client_secret))
+        .form(&params);
+
+    request_token(request).await
+}
+
+/// Refreshes an access token. Pass `client_secret: None` for tokens obtained
+/// through PKCE; the client id is then sent in the form body instead.
+pub async fn refresh_spotify_token(
+    accounts_url: &str,
+    client_id: &str,
+    client_secret: Option<&str>,
     refresh_token: &str,
-) -> Result<AuthResponse, Box<dyn Error>> {
-    let client = Client::new();
-    let params = [
+) -> Result<AuthResponse, AuthError> {
+    let client = http::client();
+    let mut params = vec![
         ("grant_type", "refresh_token"),
         ("refresh_token", refresh_token),
     ];
 
-    let response = client
-        .post("https://accounts.spotify.com/api/token")
-        .basic_auth(client_id, Some(client_secret))
-        .form(&params)
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        let mut auth_response: AuthResponse = response.json().await?;
-        // If the refresh token is not returned, use the old one
-        if auth_response.refresh_token.is_empty() {
-            auth_response.refresh_token = refresh_token.to_string();
-        }
-        Ok(auth_response)
-    } else {
-        Err(format!("Error: {}", response.status()).into())
+    let mut request = client.post(format!("{}/api/token", accounts_url));
+    match client_secret {
+        Some(secret) => request = request.basic_auth(client_id, Some(secret)),
+        None => params.push(("client_id", client_id)),
+    }
+
+    let mut auth_response = request_token(request.form(&params)).await?;
+    // If the refresh token is not returned, use the old one
+    if auth_response.refresh_token.is_empty() {
+        auth_response.refresh_token = refresh_token.to_string();
     }
+    Ok(auth_response)
+}
+
+/// Forgets a login by deleting its cached access and refresh tokens, along with
+/// the scopes recorded for them. Returns `false` if nothing was cached.
+///
+/// Spotify has no endpoint to revoke a token; the grant itself can only be
+/// revoked by the user at <https://www.spotify.com/account/apps/>.
+pub fn logout(cache: &cache::TokenCache) -> Result<bool, Box<dyn Error>> {
+    cache.clear()
 }
 
 pub fn get_auth_code(
+    accounts_url: &str,
     client_id: &str,
     redirect_uri: &str,
-    scope: &str,
+    scopes: &Scopes,
+    mode: LoginMode,
 ) -> Result<String, Box<dyn Error>> {
-    let auth_url = format!(
-        "https://accounts.spotify.com/authorize?client_id={}&response_type=code&redirect_uri={}&scope={}",
+    let state = random_string(16);
+    let auth_url = authorize_url(accounts_url, client_id, redirect_uri, scopes, &state, None);
+    receive_code(&auth_url, redirect_uri, &state, mode)
+}
+
+/// Like [`get_auth_code`], but sends the PKCE challenge so the code can later
+/// be exchanged with [`get_spotify_token_pkce`] without a client secret.
+pub fn get_auth_code_pkce(
+    accounts_url: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    scopes: &Scopes,
+    pkce: &Pkce,
+    mode: LoginMode,
+) -> Result<String, Box<dyn Error>> {
+    let state = random_string(16);
+    let auth_url = authorize_url(
+        accounts_url,
+        client_id,
+        redirect_uri,
+        scopes,
+        &state,
+        Some(&pkce.challenge),
+    );
+    receive_code(&auth_url, redirect_uri, &state, mode)
+}
+
+fn authorize_url(
+    accounts_url: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    scopes: &Scopes,
+    state: &str,
+    code_challenge: Option<&str>,
+) -> String {
+    let mut auth_url = format!(
+        "{}/authorize?client_id={}&response_type=code&redirect_uri={}&scope={}&state={}",
+        accounts_url,
         client_id,
         urlencoding::encode(redirect_uri),
-        urlencoding::encode(scope)
+        urlencoding::encode(&scopes.to_string()),
+        state
     );
+    if let Some(challenge) = code_challenge {
+        auth_url.push_str("&code_challenge_method=S256&code_challenge=");
+        auth_url.push_str(challenge);
+    }
+    auth_url
+}
 
-    // Open the authorization URL in the default web browser
-    webbrowser::open(&auth_url)?;
+fn receive_code(
+    auth_url: &str,
+    redirect_uri: &str,
+    state: &str,
+    mode: LoginMode,
+) -> Result<String, Box<dyn Error>> {
+    match mode {
+        LoginMode::Browser { timeout } => listen_for_code(auth_url, redirect_uri, state, timeout),
+        LoginMode::Headless => read_pasted_code(auth_url, state),
+    }
+}
+
+/// Headless login: the user opens `auth_url` on any machine, and pastes back
+/// either the URL the browser was redirected to or just its `code` value.
+fn read_pasted_code(auth_url: &str, state: &str) -> Result<String, Box<dyn Error>> {
+    println!(
+        "Open this URL in a browser and approve the login:\n\n{}\n",
+        auth_url
+    );
+    println!("The browser will then fail to load a localhost page. That's expected;");
+    println!("copy the full URL from its address bar (or just the code) and paste it here:");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim();
 
-    // Start a local server to listen for the callback
-    let listener = TcpListener::bind("127.0.0.1:3000")?;
-    println!("Listening for callback on http://localhost:3000/callback");
+    match Url::parse(input) {
+        // A pasted URL goes through the same checks as a real callback
+        Ok(url) => extract_code(&url, state)?.ok_or_else(|| AuthError::MissingCode.into()),
+        Err(_) if !input.is_empty() => Ok(input.to_string()),
+        Err(_) => Err(AuthError::MissingCode.into()),
+    }
+}
+
+/// Serves the redirect URI until the real callback arrives or `timeout` passes.
+/// Every request gets an answer, so stray ones like `/favicon.ico` don't hang.
+fn listen_for_code(
+    auth_url: &str,
+    redirect_uri: &str,
+    state: &str,
+    timeout: Duration,
+) -> Result<String, Box<dyn Error>> {
+    let redirect = Url::parse(redirect_uri)
+        .map_err(|_| AuthError::InvalidRedirectUri(redirect_uri.to_string()))?;
+    let host = match redirect.host_str() {
+        // Keep to IPv4 loopback; "localhost" may resolve to ::1 first
+        Some("localhost") => "127.0.0.1",
+        Some(host) => host,
+        None => return Err(AuthError::InvalidRedirectUri(redirect_uri.to_string()).into()),
+    };
+    let port = redirect
+        .port_or_known_default()
+        .ok_or_else(|| AuthError::InvalidRedirectUri(redirect_uri.to_string()))?;
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(mut stream) => {
-                let mut reader = BufReader::new(&stream);
-                let mut request_line = String::new();
-                reader.read_line(&mut request_line)?;
+    // Start a local server to listen for the callback. It is bound before the
+    // browser opens so a fast redirect can't arrive before we are listening.
+    let listener = TcpListener::bind((host, port))?;
+    // Non-blocking accepts let the loop notice the deadline between requests
+    listener.set_nonblocking(true)?;
 
-                // Extract the authorization code from the request
-                if let Some(code) = extract_code(&request_line) {
-                    // Send a response to the browser
-                    let response = "HTTP/1.1 200 OK\r\n\r\nAuthorization successful! You can close this window.";
-                    stream.write_all(response.as_bytes())?;
+    // Open the authorization URL in the default web browser
+    webbrowser::open(auth_url)?;
+    println!("Listening for callback on {}", redirect_uri);
 
+    let deadline = Instant::now() + timeout;
+    loop {
+        if Instant::now() >= deadline {
+            return Err(AuthError::Timeout(timeout).into());
+        }
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                if let Some(code) = handle_request(&mut stream, redirect.path(), state)? {
                     return Ok(code);
                 }
             }
-            Err(e) => println!("Error: {}", e),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            // Errors like running out of file descriptors tend to repeat, so
+            // wait a little longer before trying again
+            Err(e) => {
+                println!("Error: {}", e);
+                thread::sleep(Duration::from_secs(1));
+            }
         }
     }
+}
 
-    Err("Failed to get authorization code".into())
+/// Reads one request and answers it. Returns the code once the callback for
+/// this login arrives, `None` for anything else worth waiting past.
+fn handle_request(
+    stream: &mut TcpStream,
+    callback_path: &str,
+    state: &str,
+) -> Result<Option<String>, AuthError> {
+    let mut request_line = String::new();
+    // Keep the wait short: browsers open idle sockets ahead of time, and the
+    // real callback queues behind whichever one is being read
+    let read = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(Duration::from_millis(500))))
+        .and_then(|_| BufReader::new(&*stream).read_line(&mut request_line));
+    match read {
+        Ok(_) => {}
+        // An idle socket that never sent anything
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return Ok(None)
+        }
+        Err(e) => {
+            println!("Error: {}", e);
+            return Ok(None);
+        }
+    }
+
+    let url = match request_url(&request_line) {
+        Some(url) if url.path() == callback_path => url,
+        _ => {
+            send_response(stream, "404 Not Found", "Not found.");
+            return Ok(None);
+        }
+    };
+
+    // Extract the authorization code from the request
+    let result = extract_code(&url, state);
+    match &result {
+        Ok(Some(_)) => send_response(
+            stream,
+            "200 OK",
+            "Authorization successful! You can close this window.",
+        ),
+        Ok(None) => send_response(stream, "400 Bad Request", "Missing authorization code."),
+        Err(e) => send_response(stream, "400 Bad Request", &e.to_string()),
+    }
+    result
 }
 
-fn extract_code(request_line: &str) -> Option<String> {
-    let url = request_line.split_whitespace().nth(1)?;
-    let url = format!("http://localhost{}", url);
-    let parsed_url = Url::parse(&url).ok()?;
-    parsed_url
-        .query_pairs()
-        .find(|(key, _)| key == "code")
-        .map(|(_, value)| value.into_owned())
+fn send_response(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    // The browser going away doesn't affect the login itself
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        println!("Error: {}", e);
+    }
+}
+
+/// Parses the target of an HTTP request line (`GET /callback?code=... HTTP/1.1`)
+fn request_url(request_line: &str) -> Option<Url> {
+    let target = request_line.split_whitespace().nth(1)?;
+    Url::parse(&format!("http://localhost{}", target)).ok()
+}
+
+/// Returns the `code` from a callback URL, or `None` if it carries neither a
+/// code nor an error. Fails with [`AuthError::StateMismatch`] if its `state`
+/// is not `expected_state`, and with [`AuthError::AccessDenied`] or
+/// [`AuthError::Authorization`] if Spotify redirected back with an `error`.
+fn extract_code(url: &Url, expected_state: &str) -> Result<Option<String>, AuthError> {
+    let query_value = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    let code = query_value("code");
+    let error = query_value("error");
+    if code.is_none() && error.is_none() {
+        return Ok(None);
+    }
+    if query_value("state").as_deref() != Some(expected_state) {
+        return Err(AuthError::StateMismatch);
+    }
+    match error.as_deref() {
+        Some("access_denied") => Err(AuthError::AccessDenied),
+        Some(error) => Err(AuthError::Authorization(error.to_string())),
+        None => Ok(code),
+    }
 }
@@ -0,0 +1,190 @@
+//! On-disk cache for the tokens returned by the login flow, so a run can reuse
+//! (or refresh) the previous session instead of opening the browser again.
+//!
+//! The cache is written either as plain JSON or encrypted with a passphrase
+//! (see [`TokenStore`]). Loading recognises both formats, so switching the
+//! store only changes how the next save is written.
+
+use super::AuthResponse;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KDF: &str = "argon2id";
+
+/// How the cache file is written.
+#[derive(Clone, Default)]
+pub enum TokenStore {
+    #[default]
+    Plaintext,
+    /// AES-256-GCM, with the key derived from the passphrase by Argon2id
+    Encrypted { passphrase: String },
+}
+
+#[derive(Clone)]
+pub struct TokenCache {
+    path: PathBuf,
+    store: TokenStore,
+}
+
+/// The on-disk form of an encrypted cache. Binary fields are base64.
+#[derive(Serialize, Deserialize)]
+struct EncryptedTokens {
+    kdf: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl TokenCache {
+    pub fn new(path: PathBuf) -> Self {
+        TokenCache {
+            path,
+            store: TokenStore::Plaintext,
+        }
+    }
+
+    /// `<user cache dir>/spotify-hackathon/token.json`, e.g.
+    /// `~/.cache/spotify-hackathon/token.json` on Linux. `None` if the platform
+    /// has no per-user cache directory.
+    pub fn default_location() -> Option<Self> {
+        let dir = dirs::cache_dir()?.join("spotify-hackathon");
+        Some(TokenCache::new(dir.join("token.json")))
+    }
+
+    /// The same cache file, saved with `store` from now on.
+    pub fn with_store(self, store: TokenStore) -> Self {
+        TokenCache { store, ..self }
+    }
+
+    /// Returns the cached tokens, or `None` if there is no cache yet or it
+    /// can't be parsed (a corrupt cache just means logging in again).
+    ///
+    /// An encrypted cache that can't be decrypted is an error rather than
+    /// `None`: logging in again would overwrite it with the wrong passphrase.
+    pub fn load(&self) -> Result<Option<AuthResponse>, Box<dyn Error>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let Ok(encrypted) = serde_json::from_str::<EncryptedTokens>(&contents) else {
+            return Ok(serde_json::from_str(&contents).ok());
+        };
+        let TokenStore::Encrypted { passphrase } = &self.store else {
+            return Err(format!(
+                "The saved tokens in {} are encrypted; set SPOTIFY_TOKEN_STORE=encrypted to use them",
+                self.path.display()
+            )
+            .into());
+        };
+        let plaintext = decrypt(passphrase, &encrypted)?;
+        Ok(serde_json::from_slice(&plaintext).ok())
+    }
+
+    pub fn save(&self, tokens: &AuthResponse) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let json = serde_json::to_string_pretty(tokens)?;
+        let contents = match &self.store {
+            TokenStore::Plaintext => json,
+            TokenStore::Encrypted { passphrase } => {
+                serde_json::to_string_pretty(&encrypt(passphrase, json.as_bytes())?)?
+            }
+        };
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // The refresh token is a long-lived credential; keep it private to the user
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&self.path)?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    /// Rewrites the cached tokens with `store`, e.g. to encrypt an existing
+    /// plaintext cache. `self` must be able to read the current file. Returns
+    /// `false` if there was nothing to migrate.
+    pub fn migrate(&self, store: TokenStore) -> Result<bool, Box<dyn Error>> {
+        let Some(tokens) = self.load()? else {
+            return Ok(false);
+        };
+        self.clone().with_store(store).save(&tokens)?;
+        Ok(true)
+    }
+
+    /// Deletes the cached tokens. Returns `false` if there were none.
+    pub fn clear(&self) -> Result<bool, Box<dyn Error>> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Encrypts with a fresh salt and nonce, so saving the same tokens twice never
+/// produces the same file.
+fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<EncryptedTokens, Box<dyn Error>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = cipher(passphrase, &salt)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| "Could not encrypt the token cache")?;
+
+    Ok(EncryptedTokens {
+        kdf: KDF.to_string(),
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+fn decrypt(passphrase: &str, encrypted: &EncryptedTokens) -> Result<Vec<u8>, Box<dyn Error>> {
+    if encrypted.kdf != KDF {
+        return Err(format!("Unsupported token cache key derivation {:?}", encrypted.kdf).into());
+    }
+    let salt = STANDARD.decode(&encrypted.salt)?;
+    let nonce = STANDARD.decode(&encrypted.nonce)?;
+    let ciphertext = STANDARD.decode(&encrypted.ciphertext)?;
+    if nonce.len() != NONCE_LEN {
+        return Err("Corrupt token cache: bad nonce length".into());
+    }
+
+    // GCM authenticates the ciphertext, so a wrong passphrase and a tampered
+    // file fail the same way
+    let plaintext = cipher(passphrase, &salt)?
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| "Could not decrypt the saved tokens: wrong passphrase or corrupted cache")?;
+    Ok(plaintext)
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm, Box<dyn Error>> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Could not derive the token cache key: {}", e))?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
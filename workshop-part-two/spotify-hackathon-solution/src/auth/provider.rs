@@ -0,0 +1,161 @@
+//! Hands out valid access tokens to API calls, so long-running work doesn't
+//! break when the hour-long token lifetime runs out.
+
+use super::cache::TokenCache;
+use super::{get_client_credentials_token, refresh_spotify_token, AuthError, AuthResponse};
+use crate::http;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::error::Error;
+use tokio::sync::Mutex;
+
+/// Where a [`TokenProvider`] gets new tokens from.
+pub trait TokenSource {
+    /// Gets a new token. `current` is the token being replaced, if any.
+    async fn fetch(&self, current: Option<&AuthResponse>) -> Result<AuthResponse, AuthError>;
+}
+
+/// Tokens from a user login, renewed with their refresh token.
+pub struct UserTokens {
+    accounts_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    cache: Option<TokenCache>,
+}
+
+impl UserTokens {
+    /// `client_secret` is `None` for PKCE logins. Refreshed tokens are written
+    /// to `cache` so the next run starts from them.
+    pub fn new(
+        accounts_url: &str,
+        client_id: &str,
+        client_secret: Option<&str>,
+        cache: Option<TokenCache>,
+    ) -> Self {
+        UserTokens {
+            accounts_url: accounts_url.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.map(str::to_string),
+            cache,
+        }
+    }
+}
+
+impl TokenSource for UserTokens {
+    async fn fetch(&self, current: Option<&AuthResponse>) -> Result<AuthResponse, AuthError> {
+        let refresh_token = current
+            .map(|token| token.refresh_token.as_str())
+            .filter(|refresh_token| !refresh_token.is_empty())
+            .ok_or_else(|| AuthError::InvalidGrant("no refresh token stored".to_string()))?;
+
+        let mut refreshed = refresh_spotify_token(
+            &self.accounts_url,
+            &self.client_id,
+            self.client_secret.as_deref(),
+            refresh_token,
+        )
+        .await?;
+        if let Some(current) = current {
+            refreshed.inherit_scope(current);
+        }
+
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.save(&refreshed) {
+                println!("Warning: could not cache tokens: {}", e);
+            }
+        }
+        Ok(refreshed)
+    }
+}
+
+/// App-only tokens from the client-credentials grant, for endpoints that
+/// don't need a user.
+#[allow(dead_code)] // the CLI only makes user calls so far
+pub struct ClientCredentials {
+    accounts_url: String,
+    client_id: String,
+    client_secret: String,
+}
+
+#[allow(dead_code)]
+impl ClientCredentials {
+    pub fn new(accounts_url: &str, client_id: &str, client_secret: &str) -> Self {
+        ClientCredentials {
+            accounts_url: accounts_url.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+        }
+    }
+}
+
+impl TokenSource for ClientCredentials {
+    async fn fetch(&self, _current: Option<&AuthResponse>) -> Result<AuthResponse, AuthError> {
+        get_client_credentials_token(&self.accounts_url, &self.client_id, &self.client_secret).await
+    }
+}
+
+/// Shares one token between any number of concurrent API calls.
+///
+/// The token is kept behind an async mutex that stays locked while a new one
+/// is fetched, so when many tasks find the token expiring at once, one of them
+/// refreshes it and the others wait for and reuse the result.
+pub struct TokenProvider<S> {
+    source: S,
+    token: Mutex<Option<AuthResponse>>,
+}
+
+impl<S: TokenSource> TokenProvider<S> {
+    /// `token` is the token to start from, e.g. fresh from a login. With `None`
+    /// the first call fetches one from `source`.
+    pub fn new(source: S, token: Option<AuthResponse>) -> Self {
+        TokenProvider {
+            source,
+            token: Mutex::new(token),
+        }
+    }
+
+    /// Returns an access token that isn't about to expire, fetching a new one
+    /// first if needed.
+    pub async fn access_token(&self) -> Result<String, AuthError> {
+        let mut token = self.token.lock().await;
+        if let Some(current) = token.as_ref().filter(|t| !t.is_expired()) {
+            return Ok(current.access_token.clone());
+        }
+
+        let fresh = self.source.fetch(token.as_ref()).await?;
+        let access_token = fresh.access_token.clone();
+        *token = Some(fresh);
+        Ok(access_token)
+    }
+
+    /// Replaces `rejected` with a new token even though it hasn't expired yet,
+    /// unless another caller has already done so.
+    pub async fn force_refresh(&self, rejected: &str) -> Result<String, AuthError> {
+        let mut token = self.token.lock().await;
+        if let Some(current) = token.as_ref() {
+            if current.access_token != rejected && !current.is_expired() {
+                return Ok(current.access_token.clone());
+            }
+        }
+
+        let fresh = self.source.fetch(token.as_ref()).await?;
+        let access_token = fresh.access_token.clone();
+        *token = Some(fresh);
+        Ok(access_token)
+    }
+
+    /// Sends the request `build` makes for a valid access token. If Spotify
+    /// still answers 401, the token is refreshed and the request sent once more.
+    pub async fn send<F>(&self, build: F) -> Result<Response, Box<dyn Error>>
+    where
+        F: Fn(&str) -> RequestBuilder,
+    {
+        let access_token = self.access_token().await?;
+        let response = http::send(build(&access_token)).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let access_token = self.force_refresh(&access_token).await?;
+        Ok(http::send(build(&access_token)).await?)
+    }
+}
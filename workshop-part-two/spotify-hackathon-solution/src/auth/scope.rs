@@ -0,0 +1,130 @@
+//! Typed OAuth scopes. Features declare the scopes they need, and the token
+//! response records the scopes that were actually granted, so a missing one
+//! can be detected before a request fails with 403.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    UserTopRead,
+    UserReadRecentlyPlayed,
+    UserLibraryRead,
+    UserLibraryModify,
+    UserReadPrivate,
+    UserReadEmail,
+    PlaylistReadPrivate,
+    PlaylistReadCollaborative,
+    PlaylistModifyPrivate,
+    PlaylistModifyPublic,
+    UserFollowRead,
+    UserFollowModify,
+    UserReadPlaybackState,
+    UserModifyPlaybackState,
+    UserReadCurrentlyPlaying,
+    /// A scope this enum doesn't know about, kept as Spotify spelled it
+    Other(String),
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Scope::UserTopRead => "user-top-read",
+            Scope::UserReadRecentlyPlayed => "user-read-recently-played",
+            Scope::UserLibraryRead => "user-library-read",
+            Scope::UserLibraryModify => "user-library-modify",
+            Scope::UserReadPrivate => "user-read-private",
+            Scope::UserReadEmail => "user-read-email",
+            Scope::PlaylistReadPrivate => "playlist-read-private",
+            Scope::PlaylistReadCollaborative => "playlist-read-collaborative",
+            Scope::PlaylistModifyPrivate => "playlist-modify-private",
+            Scope::PlaylistModifyPublic => "playlist-modify-public",
+            Scope::UserFollowRead => "user-follow-read",
+            Scope::UserFollowModify => "user-follow-modify",
+            Scope::UserReadPlaybackState => "user-read-playback-state",
+            Scope::UserModifyPlaybackState => "user-modify-playback-state",
+            Scope::UserReadCurrentlyPlaying => "user-read-currently-playing",
+            Scope::Other(scope) => scope,
+        }
+    }
+}
+
+impl From<&str> for Scope {
+    fn from(s: &str) -> Self {
+        match s {
+            "user-top-read" => Scope::UserTopRead,
+            "user-read-recently-played" => Scope::UserReadRecentlyPlayed,
+            "user-library-read" => Scope::UserLibraryRead,
+            "user-library-modify" => Scope::UserLibraryModify,
+            "user-read-private" => Scope::UserReadPrivate,
+            "user-read-email" => Scope::UserReadEmail,
+            "playlist-read-private" => Scope::PlaylistReadPrivate,
+            "playlist-read-collaborative" => Scope::PlaylistReadCollaborative,
+            "playlist-modify-private" => Scope::PlaylistModifyPrivate,
+            "playlist-modify-public" => Scope::PlaylistModifyPublic,
+            "user-follow-read" => Scope::UserFollowRead,
+            "user-follow-modify" => Scope::UserFollowModify,
+            "user-read-playback-state" => Scope::UserReadPlaybackState,
+            "user-modify-playback-state" => Scope::UserModifyPlaybackState,
+            "user-read-currently-playing" => Scope::UserReadCurrentlyPlaying,
+            other => Scope::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A set of scopes. Converts from, displays and (de)serializes as the space-separated
+/// string OAuth uses, e.g. `"user-top-read playlist-modify-private"`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scopes(BTreeSet<Scope>);
+
+impl Scopes {
+    pub fn new(scopes: &[Scope]) -> Self {
+        Scopes(scopes.iter().cloned().collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The scopes in `required` that this set doesn't have.
+    pub fn missing(&self, required: &Scopes) -> Scopes {
+        Scopes(required.0.difference(&self.0).cloned().collect())
+    }
+
+    pub fn union(&self, other: &Scopes) -> Scopes {
+        Scopes(self.0.union(&other.0).cloned().collect())
+    }
+}
+
+impl From<&str> for Scopes {
+    fn from(s: &str) -> Self {
+        Scopes(s.split_whitespace().map(Scope::from).collect())
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scopes: Vec<&str> = self.0.iter().map(Scope::as_str).collect();
+        f.write_str(&scopes.join(" "))
+    }
+}
+
+impl Serialize for Scopes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Scopes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let scopes = String::deserialize(deserializer)?;
+        Ok(Scopes::from(scopes.as_str()))
+    }
+}
@@ -0,0 +1,160 @@
+//! Command-line parsing. Kept to plain `std::env::args` matching, since the
+//! CLI only has a handful of commands.
+
+use crate::search::{SearchQuery, SearchType, MAX_OFFSET};
+use crate::MusicAnalysisError;
+
+/// Results shown per item type, and so per `page:`
+pub const SEARCH_PAGE_SIZE: usize = 10;
+
+/// The last `page:` whose offset Spotify still accepts
+const LAST_SEARCH_PAGE: usize = MAX_OFFSET / SEARCH_PAGE_SIZE + 1;
+
+pub const USAGE: &str = "\
+Usage: spotify-hackathon [--profile <name>] [command]
+
+Commands:
+  analyze                 Roast or toast your top tracks (default)
+  playlist <name>         Save your top tracks as a private playlist
+  mood                    Read your mood from the last day's listening
+  find <text>             List liked songs whose title or artist contains <text>
+  search [<type>] <terms> Search the catalog for tracks, artists, albums and
+                          playlists, or only one <type> of them (e.g. tracks).
+                          Terms can filter with artist:, album:, track:,
+                          genre:, isrc: and year:1997 or year:1990-1999, and
+                          pick market:<country> and page:<n>
+  export <file>           Save all your liked songs to <file>, as CSV if it
+                          ends in .csv or JSON lines if it ends in .jsonl.
+                          Run it again to resume an interrupted export
+  logout                  Forget the saved login
+  tokens encrypt          Encrypt the saved login with a passphrase
+  tokens decrypt          Store the saved login as plain JSON again
+  profile list            List saved profiles
+  profile add <name>      Create or update a profile
+  profile remove <name>   Delete a profile and its saved login
+  help                    Show this message";
+
+pub enum Command {
+    Analyze,
+    Playlist(String),
+    Mood,
+    Find(String),
+    Search(SearchQuery),
+    Export(String),
+    Logout,
+    TokensEncrypt,
+    TokensDecrypt,
+    ProfileList,
+    ProfileAdd(String),
+    ProfileRemove(String),
+    Help,
+}
+
+pub struct Args {
+    /// The profile selected with `--profile`; `None` uses the environment
+    pub profile: Option<String>,
+    pub command: Command,
+}
+
+/// Parses the arguments after the program name.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, MusicAnalysisError> {
+    let mut profile = None;
+    let mut words = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| usage_error("--profile needs a name"))?;
+                profile = Some(name);
+            }
+            "-h" | "--help" => words.push("help".to_string()),
+            flag if flag.starts_with('-') => {
+                return Err(usage_error(&format!("unknown option {}", flag)))
+            }
+            _ => words.push(arg),
+        }
+    }
+
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let command = match words.as_slice() {
+        [] | ["analyze"] => Command::Analyze,
+        ["playlist", name @ ..] if !name.is_empty() => Command::Playlist(name.join(" ")),
+        ["mood"] => Command::Mood,
+        ["find", text @ ..] if !text.is_empty() => Command::Find(text.join(" ")),
+        ["search", terms @ ..] if !terms.is_empty() => Command::Search(parse_search(terms)?),
+        ["export", path] => Command::Export(path.to_string()),
+        ["logout"] => Command::Logout,
+        ["tokens", "encrypt"] => Command::TokensEncrypt,
+        ["tokens", "decrypt"] => Command::TokensDecrypt,
+        ["profile", "list"] => Command::ProfileList,
+        ["profile", "add", name] => Command::ProfileAdd(name.to_string()),
+        ["profile", "remove", name] => Command::ProfileRemove(name.to_string()),
+        ["help"] => Command::Help,
+        _ => {
+            return Err(usage_error(&format!(
+                "unknown command {:?}",
+                words.join(" ")
+            )))
+        }
+    };
+
+    Ok(Args { profile, command })
+}
+
+/// Builds a search from its words: an optional leading item type, then free
+/// text and `field:value` filters in any order.
+fn parse_search(words: &[&str]) -> Result<SearchQuery, MusicAnalysisError> {
+    let (types, words) = match words {
+        ["tracks", rest @ ..] => (vec![SearchType::Track], rest),
+        ["artists", rest @ ..] => (vec![SearchType::Artist], rest),
+        ["albums", rest @ ..] => (vec![SearchType::Album], rest),
+        ["playlists", rest @ ..] => (vec![SearchType::Playlist], rest),
+        _ => (Vec::new(), words),
+    };
+
+    let mut query = SearchQuery::new("").types(&types).limit(SEARCH_PAGE_SIZE);
+    for word in words {
+        query = match word.split_once(':') {
+            Some(("artist", name)) => query.artist(name),
+            Some(("album", name)) => query.album(name),
+            Some(("track", name)) => query.track(name),
+            Some(("genre", genre)) => query.genre(genre),
+            Some(("isrc", isrc)) => query.isrc(isrc),
+            Some(("year", years)) => match years.split_once('-') {
+                Some((from, to)) => query.years(parse_year(from)?..=parse_year(to)?),
+                None => query.year(parse_year(years)?),
+            },
+            Some(("market", market)) => query.market(market),
+            Some(("page", page)) => match page.parse::<usize>() {
+                Ok(page) if page > LAST_SEARCH_PAGE => {
+                    return Err(usage_error(&format!(
+                        "page:{} is past the last page Spotify serves ({})",
+                        page, LAST_SEARCH_PAGE
+                    )))
+                }
+                Ok(page) if page > 0 => query.offset((page - 1) * SEARCH_PAGE_SIZE),
+                _ => return Err(usage_error(&format!("page:{} is not a page number", page))),
+            },
+            // Anything else, including filters Spotify knows but we don't
+            // (e.g. `tag:new`), is passed on as it is
+            _ => query.text(word),
+        };
+    }
+
+    if query.is_empty() {
+        return Err(usage_error("search needs something to search for"));
+    }
+    Ok(query)
+}
+
+fn parse_year(year: &str) -> Result<u16, MusicAnalysisError> {
+    year.parse()
+        .map_err(|_| usage_error(&format!("{:?} is not a year", year)))
+}
+
+fn usage_error(message: &str) -> MusicAnalysisError {
+    MusicAnalysisError::UserInput(format!("{} (run `help` for usage)", message))
+}
@@ -0,0 +1,239 @@
+//! Writing the Liked Songs library to a file, one row per saved track, as
+//! JSON lines or CSV.
+//!
+//! Large libraries take hundreds of requests, so progress is checkpointed in
+//! `<file>.progress` after every page. Running the same export again after an
+//! interruption drops anything written after the last checkpoint and carries
+//! on from there; a finished export removes its checkpoint. An export never
+//! overwrites a file it didn't start.
+
+use crate::model::Track;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line
+    JsonLines,
+    /// With a header row
+    Csv,
+}
+
+impl ExportFormat {
+    /// The format a file name's extension asks for: `.jsonl` (or `.ndjson`)
+    /// or `.csv`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "jsonl" | "ndjson" => Some(ExportFormat::JsonLines),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// One exported row: a saved track, flattened so it fits a CSV row as well.
+#[derive(Serialize, Debug)]
+pub struct ExportRecord {
+    pub added_at: DateTime<Utc>,
+    pub id: Option<String>,
+    pub name: String,
+    /// Separated by `; `
+    pub artists: String,
+    pub album: Option<String>,
+    pub release_date: Option<String>,
+    pub duration_ms: u64,
+    pub popularity: u32,
+    pub explicit: bool,
+    pub isrc: Option<String>,
+    pub uri: String,
+    pub url: String,
+}
+
+impl ExportRecord {
+    pub fn new(added_at: DateTime<Utc>, track: &Track) -> Self {
+        let artists = track
+            .artists
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        ExportRecord {
+            added_at,
+            id: track.id.clone(),
+            name: track.name.clone(),
+            artists,
+            album: track.album.as_ref().map(|album| album.name.clone()),
+            release_date: track
+                .album
+                .as_ref()
+                .and_then(|album| album.release_date.clone()),
+            duration_ms: track.duration_ms,
+            popularity: track.popularity,
+            explicit: track.explicit,
+            isrc: track.external_ids.isrc.clone(),
+            uri: track.uri.clone(),
+            url: track.external_urls.spotify.clone(),
+        }
+    }
+}
+
+/// How far an export got: the first `offset` saved tracks of a library of
+/// `total` are in the first `bytes` bytes of the file, the last of them being
+/// `last_id`, saved at `last_added_at`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Checkpoint {
+    offset: u32,
+    total: u32,
+    bytes: u64,
+    #[serde(default)]
+    last_id: Option<String>,
+    #[serde(default)]
+    last_added_at: Option<DateTime<Utc>>,
+}
+
+pub struct Export {
+    format: ExportFormat,
+    file: File,
+    progress_path: PathBuf,
+    checkpoint: Checkpoint,
+}
+
+impl Export {
+    /// Opens the export to `path`, resuming it if an earlier run left a
+    /// checkpoint, or starting it afresh otherwise.
+    pub fn open(path: &Path, format: ExportFormat) -> io::Result<Self> {
+        let mut progress_path = path.as_os_str().to_owned();
+        progress_path.push(".progress");
+        let progress_path = PathBuf::from(progress_path);
+
+        let checkpoint = match fs::read_to_string(&progress_path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Checkpoint::default(),
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        if checkpoint.offset == 0 && len > 0 {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                "the file already exists and isn't an unfinished export; delete it or pick another name",
+            ));
+        }
+        if len < checkpoint.bytes {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "the file is shorter than its checkpoint says; delete {} to start over",
+                    progress_path.display()
+                ),
+            ));
+        }
+        // Rows written after the checkpoint may be a partial page
+        file.set_len(checkpoint.bytes)?;
+
+        Ok(Export {
+            format,
+            file,
+            progress_path,
+            checkpoint,
+        })
+    }
+
+    /// How many saved tracks are already written, and so the offset to
+    /// fetch from next.
+    pub fn offset(&self) -> u32 {
+        self.checkpoint.offset
+    }
+
+    /// Checks a page's `total` against the one the export started with.
+    /// Liked Songs are listed newest first, so a song liked or removed in
+    /// the meantime shifts every offset; the export then starts over rather
+    /// than skip or repeat songs. Returns whether it did.
+    pub fn restart_if_changed(&mut self, total: u32) -> io::Result<bool> {
+        let changed = self.checkpoint.offset > 0 && self.checkpoint.total != total;
+        if changed {
+            self.restart()?;
+        }
+        self.checkpoint.total = total;
+        Ok(changed)
+    }
+
+    /// Checks the saved track now at `offset() - 1` against the last one
+    /// written. Liking one song and removing another leaves `total` alone but
+    /// still shifts the offsets, so unless it's the same song saved at the
+    /// same time, the export starts over. Returns whether it did.
+    pub fn restart_if_moved(
+        &mut self,
+        id: Option<&str>,
+        added_at: Option<DateTime<Utc>>,
+    ) -> io::Result<bool> {
+        let moved = self.checkpoint.offset > 0
+            && (self.checkpoint.last_id.as_deref() != id
+                || self.checkpoint.last_added_at != added_at);
+        if moved {
+            self.restart()?;
+        }
+        Ok(moved)
+    }
+
+    fn restart(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.checkpoint = Checkpoint::default();
+        self.save_checkpoint()
+    }
+
+    /// Appends `records`, the page at [`offset`](Self::offset), and
+    /// checkpoints after them once they're safely on disk.
+    pub fn write_page(&mut self, records: &[ExportRecord]) -> io::Result<()> {
+        let mut rows = Vec::new();
+        match self.format {
+            ExportFormat::JsonLines => {
+                for record in records {
+                    serde_json::to_writer(&mut rows, record)?;
+                    rows.push(b'\n');
+                }
+            }
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(self.checkpoint.bytes == 0)
+                    .from_writer(&mut rows);
+                for record in records {
+                    writer.serialize(record)?;
+                }
+                writer.flush()?;
+            }
+        }
+        self.file.write_all(&rows)?;
+        self.file.sync_data()?;
+
+        self.checkpoint.offset += records.len() as u32;
+        self.checkpoint.bytes += rows.len() as u64;
+        if let Some(last) = records.last() {
+            self.checkpoint.last_id = last.id.clone();
+            self.checkpoint.last_added_at = Some(last.added_at);
+        }
+        self.save_checkpoint()
+    }
+
+    /// Marks the export complete by removing its checkpoint.
+    pub fn finish(self) -> io::Result<()> {
+        match fs::remove_file(&self.progress_path) {
+            // An empty library never gets a checkpoint
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Writes the checkpoint to a temporary file first, so an interruption
+    /// can't leave a half-written one behind.
+    fn save_checkpoint(&self) -> io::Result<()> {
+        let mut temp_path = self.progress_path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, serde_json::to_string(&self.checkpoint)?)?;
+        fs::rename(&temp_path, &self.progress_path)
+    }
+}
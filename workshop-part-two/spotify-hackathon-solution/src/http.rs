@@ -0,0 +1,193 @@
+//! The one place HTTP requests are sent from. Every request shares a client
+//! (and so its connection pool), and is retried when Spotify asks us to slow
+//! down (429 with `Retry-After`) or fails transiently (5xx, connection errors),
+//! with jittered exponential backoff and a cap on the total time spent.
+
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Backoff before the first retry; doubled for each one after that.
+const BASE_DELAY: Duration = Duration::from_millis(500);
+/// The longest backoff between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(8);
+/// Give up rather than retry if the next attempt would start later than this
+/// after the first.
+const MAX_RETRY_TIME: Duration = Duration::from_secs(30);
+
+/// The client every request is built from.
+pub fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(Client::new)
+}
+
+/// Sends `request`, retrying rate-limited and transient failures. When the
+/// retry budget runs out, the last response (e.g. the 429 itself) or error is
+/// returned for the caller to handle like any other.
+///
+/// Only idempotent requests are retried after a 5xx or a timeout, since the
+/// first attempt may already have taken effect; a POST is only retried when
+/// it never reached Spotify or was rate limited. Requests whose body can't be
+/// cloned (streams) are sent once.
+pub async fn send(request: RequestBuilder) -> Result<Response, reqwest::Error> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    let idempotent = is_idempotent(request.method());
+    let start = Instant::now();
+    let mut attempt = 0;
+    loop {
+        let Some(this_attempt) = request.try_clone() else {
+            return client.execute(request).await;
+        };
+        let result = client.execute(this_attempt).await;
+
+        let (reason, delay) = match &result {
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => (
+                "Rate limited by Spotify".to_string(),
+                retry_after(response).unwrap_or_else(|| backoff(attempt)),
+            ),
+            Ok(response) if idempotent && is_transient(response.status()) => (
+                format!("Spotify returned {}", response.status()),
+                backoff(attempt),
+            ),
+            Err(e) if e.is_connect() || (idempotent && e.is_timeout()) => {
+                ("Could not reach Spotify".to_string(), backoff(attempt))
+            }
+            _ => return result,
+        };
+        if start.elapsed() + delay > MAX_RETRY_TIME {
+            return result;
+        }
+
+        eprintln!("{}; retrying in {:.1}s...", reason, delay.as_secs_f32());
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// The delay a 429 asks for. Spotify sends whole seconds.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    Some(Duration::from_secs(seconds.trim().parse().ok()?))
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Whether sending a request with this method twice has the same effect as
+/// sending it once.
+fn is_idempotent(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::PUT | Method::DELETE)
+}
+
+/// Exponential backoff with "equal jitter": somewhere between half and all of
+/// the doubled delay, so clients that failed together don't retry together.
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_DELAY);
+    let half = ceiling / 2;
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Starts a local server that answers one request per connection with
+    /// each of `responses` in turn (a status line, optionally followed by
+    /// header lines). Returns its URL and a count of the requests it got.
+    async fn stub(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                read_request(&mut stream).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let reply = format!(
+                    "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    response
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    /// Reads a request's head and body, so the reply isn't sent early.
+    async fn read_request(stream: &mut TcpStream) {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        loop {
+            let read = stream.read(&mut buf).await.unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buf[..read]);
+            let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8_lossy(&request[..end]).to_lowercase();
+            let body_len = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|len| len.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if request.len() >= end + 4 + body_len {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_a_429_after_retry_after() {
+        let (url, requests) = stub(vec!["429 Too Many Requests\r\nretry-after: 1", "200 OK"]).await;
+
+        let start = Instant::now();
+        let response = send(Client::new().get(&url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_a_post_after_a_5xx() {
+        let (url, requests) = stub(vec!["503 Service Unavailable", "200 OK"]).await;
+
+        let request = Client::new().post(&url).body(r#"{"name":"Top tracks"}"#);
+        let response = send(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_the_wait_would_pass_the_retry_time() {
+        let (url, requests) =
+            stub(vec!["429 Too Many Requests\r\nretry-after: 60", "200 OK"]).await;
+
+        let start = Instant::now();
+        let response = send(Client::new().get(&url)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < MAX_RETRY_TIME);
+    }
+}
@@ -8,58 +8,96 @@
 //! - **Iterators**: Custom iterator implementations
 //! - **Generic Programming**: Type-safe abstractions
 
+mod audio_features;
 mod auth;
+mod cli;
+mod export;
+mod http;
+mod model;
+mod paging;
+mod playlist;
+mod profile;
+mod search;
 
-use auth::get_auth_code;
-use dialoguer::Input;
+use auth::cache::{TokenCache, TokenStore};
+use auth::provider::{TokenProvider, TokenSource, UserTokens};
+use auth::scope::{Scope, Scopes};
+use auth::{get_auth_code, get_auth_code_pkce, AuthError, AuthResponse, LoginMode, Pkce};
+use chrono::{DateTime, Local, Utc};
+use cli::{Args, Command};
+use dialoguer::{Input, Password};
 use dotenv::dotenv;
+use export::{Export, ExportFormat, ExportRecord};
+use futures::{future, Stream, StreamExt, TryStreamExt};
+use model::{Album, Artist, Track};
 use openai::chat::{ChatCompletion, ChatCompletionMessage, ChatCompletionMessageRole};
 use openai::set_key;
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
-use reqwest::Client;
+use paging::{CursorPaging, Paging};
+use playlist::Playlist;
+use profile::{Profile, ProfileStore};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use search::SearchQuery;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
 
 /// # Data Structures for Spotify API Responses
 ///
 /// These structs represent the JSON responses from Spotify's Web API.
 /// They use `#[derive(Deserialize)]` to automatically parse JSON into Rust structs.
+/// The catalog objects themselves (`Track`, `Artist`, ...) are in `model`;
+/// these are the wrappers particular endpoints put around them.
 
-/// Represents a single track from Spotify
+/// A track in the user's Liked Songs
 ///
-/// **Rust Concept: Derive Macros**
-/// - `#[derive(Deserialize, Debug, Clone)]` automatically implements:
-///   - `Deserialize`: Converts JSON to this struct
-///   - `Debug`: Allows printing with `{:?}`
-///   - `Clone`: Allows creating copies of the struct
-#[derive(Deserialize, Debug, Clone)]
-struct Track {
-    name: String,
-    artists: Vec<Artist>,
+/// **Rust Concept: Nested Deserialization**
+/// Spotify wraps each saved track in an object, so `track` is itself a
+/// `Track` parsed with that struct's own `Deserialize` impl.
+#[derive(Deserialize, Debug)]
+struct SavedTrack {
+    /// When the user liked the track
+    added_at: DateTime<Utc>,
+    track: Track,
 }
 
-/// Represents an artist from Spotify
+/// One play from the user's listening history
 ///
-/// **Rust Concept: Owned vs Borrowed Data**
-/// - `String` is an owned type (we own the memory)
-/// - `&str` would be a borrowed reference (we don't own it)
-#[derive(Deserialize, Debug, Clone)]
-struct Artist {
-    name: String,
+/// **Rust Concept: Parsing Into Richer Types**
+/// `played_at` arrives as an ISO 8601 string; with chrono's `serde` feature
+/// it deserializes straight into a `DateTime<Utc>` that can be compared
+/// and converted to local time.
+#[derive(Deserialize, Debug)]
+struct PlayHistory {
+    track: Track,
+    played_at: DateTime<Utc>,
+    /// What the track was played from; `None` when it wasn't an album,
+    /// artist or playlist (e.g. a search result)
+    context: Option<PlayContext>,
 }
 
-/// Represents the response from Spotify's top tracks endpoint
-///
-/// **Rust Concept: Collections**
-/// - `Vec<Track>` is a growable array of owned `Track` instances
-/// - Each `Track` in the vector is owned by the vector
 #[derive(Deserialize, Debug)]
-struct TopTracksResponse {
-    items: Vec<Track>,
+struct PlayContext {
+    /// `album`, `artist`, `playlist` or `show`
+    #[serde(rename = "type")]
+    kind: String,
 }
 
+/// Represents the response from Spotify's top tracks endpoint
+///
+/// **Rust Concept: Type Aliases and Generics**
+/// Every Spotify list comes back in the same paging object, so this is just
+/// the generic `Paging<T>` filled in with `Track`. `items` is a `Vec<Track>`,
+/// a growable array that owns each `Track`.
+type TopTracksResponse = Paging<Track>;
+
 /// # Custom Error Type - Rust Error Handling Pattern
 ///
 /// **Rust Concept: Custom Error Types**
@@ -72,6 +110,11 @@ struct TopTracksResponse {
 /// - Specific error messages for each failure mode
 /// - Allows pattern matching on error types
 /// - Better debugging and user experience
+///
+/// **Rust Concept: Struct-like Enum Variants**
+/// `RateLimited { retry_after }` and `Decode { path, source }` name their
+/// fields, which reads better than a tuple when a variant carries several
+/// values or ones of the same type.
 #[derive(Debug)]
 enum MusicAnalysisError {
     /// Authentication errors from Spotify API
@@ -82,6 +125,86 @@ enum MusicAnalysisError {
     UserInput(String),
     /// Network or HTTP errors
     NetworkError(String),
+    /// The request never got a response (connection refused, timeout, ...)
+    Request(reqwest::Error),
+    /// 401: Spotify rejected the access token, even after refreshing it
+    Unauthorized(String),
+    /// 403: the login lacks a scope the request needs, or the app isn't
+    /// allowed to use the endpoint
+    InsufficientScope(String),
+    /// 404: the track, artist or playlist doesn't exist
+    NotFound(String),
+    /// 429 that lasted longer than the HTTP layer was willing to wait
+    RateLimited { retry_after: Option<Duration> },
+    /// 5xx: a problem on Spotify's side
+    ServerError { status: StatusCode, message: String },
+    /// Any other unsuccessful status
+    Api { status: StatusCode, message: String },
+    /// The response wasn't the JSON we expected; `path` points at the
+    /// offending value, e.g. `items[3].artists`
+    Decode {
+        path: String,
+        source: serde_json::Error,
+    },
+    /// Reading or writing an export file failed
+    Export { path: PathBuf, source: io::Error },
+}
+
+/// The `{"error": {"status": .., "message": ..}}` body of a Web API error
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    error: ApiErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorDetail {
+    message: String,
+}
+
+impl MusicAnalysisError {
+    /// The error for an unsuccessful Web API response, chosen by its status
+    ///
+    /// **Rust Concept: Consuming `self`**
+    /// Reading the body takes ownership of the `Response`, so this takes it
+    /// by value; the status and headers are copied out first.
+    async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|seconds| seconds.trim().parse().ok())
+            .map(Duration::from_secs);
+
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ApiErrorBody>(&body)
+            .map(|body| body.error.message)
+            .unwrap_or_else(|_| status.canonical_reason().unwrap_or("").to_string());
+
+        match status {
+            StatusCode::UNAUTHORIZED => MusicAnalysisError::Unauthorized(message),
+            StatusCode::FORBIDDEN => MusicAnalysisError::InsufficientScope(message),
+            StatusCode::NOT_FOUND => MusicAnalysisError::NotFound(message),
+            StatusCode::TOO_MANY_REQUESTS => MusicAnalysisError::RateLimited { retry_after },
+            status if status.is_server_error() => {
+                MusicAnalysisError::ServerError { status, message }
+            }
+            status => MusicAnalysisError::Api { status, message },
+        }
+    }
+}
+
+/// Parses a JSON response body into `T`
+///
+/// **Rust Concept: Generic Return Types**
+/// The caller picks `T` (usually by the type it assigns the result to), and
+/// `serde_path_to_error` records where in the document parsing failed.
+fn decode_json<T: DeserializeOwned>(body: &str) -> Result<T, MusicAnalysisError> {
+    let mut deserializer = serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|e| MusicAnalysisError::Decode {
+        path: e.path().to_string(),
+        source: e.into_inner(),
+    })
 }
 
 /// **Rust Concept: Implementing Display Trait**
@@ -96,6 +219,46 @@ impl fmt::Display for MusicAnalysisError {
             MusicAnalysisError::OpenAIError(msg) => write!(f, "OpenAI API error: {}", msg),
             MusicAnalysisError::UserInput(msg) => write!(f, "User input error: {}", msg),
             MusicAnalysisError::NetworkError(msg) => write!(f, "Network error: {}", msg),
+            MusicAnalysisError::Request(_) => write!(
+                f,
+                "Could not reach Spotify — check your connection and try again"
+            ),
+            MusicAnalysisError::Unauthorized(msg) => write!(
+                f,
+                "Spotify rejected the login ({}) — run logout, then try again to log in afresh",
+                msg
+            ),
+            MusicAnalysisError::InsufficientScope(msg) => write!(
+                f,
+                "Spotify refused the request ({}) — the login may lack a scope this needs, \
+                 or the app may not be allowed to use this feature",
+                msg
+            ),
+            MusicAnalysisError::NotFound(msg) => write!(f, "Not found on Spotify: {}", msg),
+            MusicAnalysisError::RateLimited {
+                retry_after: Some(delay),
+            } => write!(
+                f,
+                "Spotify is rate limiting requests — try again in {} seconds",
+                delay.as_secs()
+            ),
+            MusicAnalysisError::RateLimited { retry_after: None } => {
+                write!(f, "Spotify is rate limiting requests — try again later")
+            }
+            MusicAnalysisError::ServerError { status, message } => write!(
+                f,
+                "Spotify is having problems ({}: {}) — try again later",
+                status, message
+            ),
+            MusicAnalysisError::Api { status, message } => {
+                write!(f, "Spotify API error {}: {}", status, message)
+            }
+            MusicAnalysisError::Decode { path, .. } => {
+                write!(f, "Unexpected response from Spotify at `{}`", path)
+            }
+            MusicAnalysisError::Export { path, .. } => {
+                write!(f, "Could not write the export to {}", path.display())
+            }
         }
     }
 }
@@ -103,17 +266,80 @@ impl fmt::Display for MusicAnalysisError {
 /// **Rust Concept: Implementing Error Trait**
 /// This makes our custom error type compatible with Rust's error handling ecosystem.
 /// The `Error` trait is required for `?` operator and error propagation.
-impl Error for MusicAnalysisError {}
+///
+/// **Rust Concept: Error Chaining**
+/// `source()` hands out the lower-level error a variant wraps, so tools
+/// that walk the chain can show the underlying cause as well.
+impl Error for MusicAnalysisError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MusicAnalysisError::Request(e) => Some(e),
+            MusicAnalysisError::Decode { source, .. } => Some(source),
+            MusicAnalysisError::Export { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
 /// **Rust Concept: From Trait Implementation**
 /// This allows automatic conversion from `Box<dyn Error>` to our custom error type.
 /// The `From` trait is used by the `?` operator for automatic error conversion.
+///
+/// **Rust Concept: Downcasting**
+/// `downcast::<T>()` checks whether the boxed error is really a `T` and
+/// gives it back if so, letting auth and connection errors keep their
+/// specific variants.
 impl From<Box<dyn Error>> for MusicAnalysisError {
     fn from(err: Box<dyn Error>) -> Self {
-        MusicAnalysisError::NetworkError(err.to_string())
+        let err = match err.downcast::<AuthError>() {
+            Ok(auth_error) => return (*auth_error).into(),
+            Err(err) => err,
+        };
+        match err.downcast::<reqwest::Error>() {
+            Ok(request_error) => MusicAnalysisError::Request(*request_error),
+            Err(err) => MusicAnalysisError::NetworkError(err.to_string()),
+        }
     }
 }
 
+/// **Rust Concept: Converting Between Error Types**
+/// Each `AuthError` variant becomes a `SpotifyAuth` error whose message tells
+/// the user what to do about it, so `?` on an auth call gives useful advice.
+impl From<AuthError> for MusicAnalysisError {
+    fn from(err: AuthError) -> Self {
+        let message = match &err {
+            AuthError::InvalidGrant(description) => format!(
+                "{} — the login has expired or been revoked, run logout, then any command to log in again",
+                description
+            ),
+            AuthError::CodeRejected(description) => format!(
+                "{} — the authorization code was rejected, check that the redirect URI matches the one registered for the app and try again",
+                description
+            ),
+            AuthError::InvalidClient(description) => format!(
+                "{} — check SPOTIFY_CLIENT_ID and SPOTIFY_CLIENT_SECRET",
+                description
+            ),
+            AuthError::UnsupportedGrantType(description) => format!(
+                "{} — check the app's settings in the Spotify developer dashboard",
+                description
+            ),
+            AuthError::Network(e) => format!(
+                "could not reach Spotify ({}) — check your connection and try again",
+                e
+            ),
+            AuthError::AccessDenied => {
+                "access was denied in the browser — run the command again and accept".to_string()
+            }
+            _ => err.to_string(),
+        };
+        MusicAnalysisError::SpotifyAuth(message)
+    }
+}
+
+/// Represents the response from Spotify's top artists endpoint
+type TopArtistsResponse = Paging<Artist>;
+
 /// **Rust Concept: Implementing Display for Custom Types**
 /// This allows `TopTracksResponse` to be printed directly with `println!("{}", response)`.
 /// The `Formatter<'_>` uses an anonymous lifetime `'_` which means "any lifetime".
@@ -126,7 +352,8 @@ impl std::fmt::Display for TopTracksResponse {
                 .map(|a| a.name.as_str())
                 .collect::<Vec<_>>()
                 .join(", ");
-            writeln!(f, "{}. {} by {}", i + 1, track.name, artists)?;
+            let rank = self.offset as usize + i + 1;
+            writeln!(f, "{}. {} by {}", rank, track.name, artists)?;
         }
         Ok(())
     }
@@ -136,12 +363,25 @@ impl std::fmt::Display for TopTracksResponse {
 ///
 /// **Rust Concept: Clone Trait**
 /// `#[derive(Clone)]` allows creating copies of the config without taking ownership.
+///
+/// **Rust Concept: Option for Optional Values**
+/// `client_secret` is `Option<String>`: when it is `None` we log in with PKCE,
+/// so the CLI can be shared without shipping the app secret.
 #[derive(Clone)]
 struct SpotifyConfig {
     client_id: String,
-    client_secret: String,
+    client_secret: Option<String>,
     redirect_uri: String,
-    scope: String,
+    /// Scopes to request on every login, on top of what each command needs
+    scopes: Scopes,
+    /// Base URL of the accounts service (authorize and token endpoints)
+    accounts_url: String,
+    /// Base URL of the Web API, without the `/v1` prefix
+    api_url: String,
+    /// Browser login with a deadline, or headless copy-and-paste login
+    login_mode: LoginMode,
+    /// Where this config's tokens are saved between runs
+    token_cache: Option<TokenCache>,
 }
 
 impl SpotifyConfig {
@@ -150,20 +390,147 @@ impl SpotifyConfig {
     /// **Rust Concept: Error Handling with Custom Types**
     /// Returns `Result<Self, MusicAnalysisError>` instead of generic error types
     /// for better error handling and debugging.
+    ///
+    /// `SPOTIFY_ACCOUNTS_URL` and `SPOTIFY_API_URL` override the Spotify base
+    /// URLs, e.g. to point the app at a local mock server.
+    /// `SPOTIFY_LOGIN_TIMEOUT_SECS` sets how long to wait for the browser login.
+    /// `SPOTIFY_LOGIN_HEADLESS=1` (the default over SSH) logs in without a
+    /// local browser by pasting the redirected URL back into the terminal.
+    /// `SPOTIFY_SCOPES` lists extra scopes (space-separated) to always request.
+    /// `SPOTIFY_TOKEN_STORE=encrypted` encrypts the saved tokens with
+    /// `SPOTIFY_TOKEN_PASSPHRASE` (prompted for if unset).
     fn from_env() -> Result<Self, MusicAnalysisError> {
+        let client_id = env::var("SPOTIFY_CLIENT_ID").map_err(|_| {
+            MusicAnalysisError::SpotifyAuth("SPOTIFY_CLIENT_ID not set".to_string())
+        })?;
+        let scopes = env::var("SPOTIFY_SCOPES")
+            .map(|scopes| Scopes::from(scopes.as_str()))
+            .unwrap_or_default();
+
+        SpotifyConfig::new(
+            Profile {
+                client_id,
+                redirect_uri: DEFAULT_REDIRECT_URI.to_string(),
+                scopes,
+            },
+            TokenCache::default_location(),
+        )
+    }
+
+    /// Creates a config for the saved profile `name`
+    ///
+    /// The profile supplies the client id, redirect URI and scopes and has
+    /// its own token cache; everything else still comes from the environment.
+    fn from_profile(name: &str) -> Result<Self, MusicAnalysisError> {
+        let profile = profile_store()?
+            .load(name)
+            .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+        SpotifyConfig::new(profile, profile::token_cache(name))
+    }
+
+    /// **Rust Concept: Struct Destructuring**
+    /// `let Profile { .. } = profile;` moves each field out of the profile
+    /// into its own variable, ready to be moved into the config.
+    fn new(profile: Profile, token_cache: Option<TokenCache>) -> Result<Self, MusicAnalysisError> {
+        let Profile {
+            client_id,
+            redirect_uri,
+            scopes,
+        } = profile;
+
+        // The secret in the environment belongs to SPOTIFY_CLIENT_ID's app; a
+        // profile for any other app logs in with PKCE instead
+        let client_secret = match env::var("SPOTIFY_CLIENT_ID") {
+            Ok(env_client_id) if env_client_id == client_id => {
+                env::var("SPOTIFY_CLIENT_SECRET").ok()
+            }
+            _ => None,
+        };
+
         Ok(SpotifyConfig {
-            client_id: env::var("SPOTIFY_CLIENT_ID").map_err(|_| {
-                MusicAnalysisError::SpotifyAuth("SPOTIFY_CLIENT_ID not set".to_string())
-            })?,
-            client_secret: env::var("SPOTIFY_CLIENT_SECRET").map_err(|_| {
-                MusicAnalysisError::SpotifyAuth("SPOTIFY_CLIENT_SECRET not set".to_string())
-            })?,
-            redirect_uri: "http://localhost:3000/callback".to_string(),
-            scope: "user-top-read".to_string(),
+            client_id,
+            client_secret,
+            redirect_uri,
+            scopes,
+            accounts_url: base_url_from_env("SPOTIFY_ACCOUNTS_URL", auth::DEFAULT_ACCOUNTS_URL),
+            api_url: base_url_from_env("SPOTIFY_API_URL", DEFAULT_API_URL),
+            login_mode: login_mode_from_env()?,
+            token_cache: match token_cache {
+                Some(cache) => Some(cache.with_store(token_store_from_env()?)),
+                None => None,
+            },
         })
     }
 }
 
+const DEFAULT_REDIRECT_URI: &str = "http://localhost:3000/callback";
+
+/// **Rust Concept: Enums with Data**
+/// `LoginMode::Browser { timeout }` carries its deadline, while
+/// `LoginMode::Headless` needs no extra data.
+fn login_mode_from_env() -> Result<LoginMode, MusicAnalysisError> {
+    let headless = match env::var("SPOTIFY_LOGIN_HEADLESS") {
+        Ok(value) => matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"),
+        // There's rarely a usable browser on the far side of an SSH session
+        Err(_) => env::var("SSH_CONNECTION").is_ok(),
+    };
+    if headless {
+        return Ok(LoginMode::Headless);
+    }
+
+    let timeout = match env::var("SPOTIFY_LOGIN_TIMEOUT_SECS") {
+        Ok(secs) => Duration::from_secs(secs.parse().map_err(|_| {
+            MusicAnalysisError::UserInput(format!(
+                "SPOTIFY_LOGIN_TIMEOUT_SECS must be a number of seconds, got {:?}",
+                secs
+            ))
+        })?),
+        Err(_) => DEFAULT_LOGIN_TIMEOUT,
+    };
+    Ok(LoginMode::Browser { timeout })
+}
+
+/// Plaintext unless `SPOTIFY_TOKEN_STORE=encrypted`
+fn token_store_from_env() -> Result<TokenStore, MusicAnalysisError> {
+    match env::var("SPOTIFY_TOKEN_STORE").as_deref() {
+        Ok("plaintext") | Err(_) => Ok(TokenStore::Plaintext),
+        Ok("encrypted") => Ok(TokenStore::Encrypted {
+            passphrase: token_passphrase(false)?,
+        }),
+        Ok(other) => Err(MusicAnalysisError::UserInput(format!(
+            "SPOTIFY_TOKEN_STORE must be \"plaintext\" or \"encrypted\", got {:?}",
+            other
+        ))),
+    }
+}
+
+/// `SPOTIFY_TOKEN_PASSPHRASE`, or asks for it without echoing it. `confirm`
+/// asks twice, for when a typo would lock the tokens away.
+fn token_passphrase(confirm: bool) -> Result<String, MusicAnalysisError> {
+    if let Ok(passphrase) = env::var("SPOTIFY_TOKEN_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    let mut prompt = Password::new();
+    prompt.with_prompt("Token cache passphrase");
+    if confirm {
+        prompt.with_confirmation("Repeat the passphrase", "The passphrases don't match");
+    }
+    prompt
+        .interact()
+        .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))
+}
+
+const DEFAULT_LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+const DEFAULT_API_URL: &str = "https://api.spotify.com";
+
+/// Reads a base URL override, dropping any trailing `/` so paths can be appended
+fn base_url_from_env(var: &str, default: &str) -> String {
+    env::var(var)
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| default.to_string())
+}
+
 /// # Traits - Defining Shared Behavior
 ///
 /// **Rust Concept: Traits**
@@ -195,6 +562,51 @@ impl Formattable for Track {
     }
 }
 
+/// **Rust Concept: Implementing a Trait for Several Types**
+/// `Artist` gets its own `format()`, so generic code that only knows it
+/// has something `Formattable` can print tracks and artists alike.
+impl Formattable for Artist {
+    fn format(&self) -> String {
+        let mut formatted = format!(
+            "{} ({} followers, popularity {})",
+            self.name, self.followers.total, self.popularity
+        );
+        if !self.genres.is_empty() {
+            formatted.push_str(&format!(" - {}", self.genres.join(", ")));
+        }
+        formatted
+    }
+}
+
+impl Formattable for Album {
+    fn format(&self) -> String {
+        let artists = self
+            .artists
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut formatted = format!("{} by {}", self.name, artists);
+        // The year is all that every precision has in common
+        if let Some(year) = self.release_date.as_deref().and_then(|d| d.get(..4)) {
+            formatted.push_str(&format!(" ({})", year));
+        }
+        formatted
+    }
+}
+
+impl Formattable for Playlist {
+    fn format(&self) -> String {
+        match &self.owner.display_name {
+            Some(owner) => format!(
+                "{} by {} - {}",
+                self.name, owner, self.external_urls.spotify
+            ),
+            None => format!("{} - {}", self.name, self.external_urls.spotify),
+        }
+    }
+}
+
 /// # Custom Iterator Implementation
 ///
 /// **Rust Concept: Custom Iterators**
@@ -256,70 +668,330 @@ impl<'a> Iterator for TrackIterator<'a> {
 /// All functions return `Result<T, MusicAnalysisError>` for proper error handling.
 /// This allows callers to handle errors appropriately and provides better debugging.
 
-/// Fetches top tracks from Spotify API
+/// Scopes [`get_top_tracks`] needs
+const TOP_TRACKS_SCOPES: &[Scope] = &[Scope::UserTopRead];
+
+/// Fetches up to `limit` top tracks from Spotify API
 ///
 /// **Rust Concept: String Slices vs Owned Strings**
 /// - `&str` parameters are borrowed string slices (efficient)
 /// - `String` would be owned strings (requires allocation)
 ///
-/// **Rust Concept: Error Mapping**
-/// `.map_err()` converts errors from the underlying library to our custom error type.
-async fn get_top_tracks(
-    access_token: &str,
+/// **Rust Concept: Generic Functions**
+/// `S: TokenSource` lets this work with any kind of token provider, and
+/// `paging::fetch_pages` works out from the return type that each item is a
+/// `Track`. Spotify caps a page at 50 tracks, so larger limits take several
+/// requests.
+async fn get_top_tracks<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
     time_range: &str,
-    limit: u32,
+    limit: usize,
 ) -> Result<TopTracksResponse, MusicAnalysisError> {
-    let client = Client::new();
-    let url = "https://api.spotify.com/v1/me/top/tracks";
-
-    let response = client
-        .get(url)
-        .header(AUTHORIZATION, format!("Bearer {}", access_token))
-        .header(CONTENT_TYPE, "application/json")
-        .query(&[("time_range", time_range), ("limit", &limit.to_string())])
-        .send()
-        .await
-        .map_err(|e| MusicAnalysisError::NetworkError(e.to_string()))?;
+    let url = format!("{}/v1/me/top/tracks", api_url);
+    paging::fetch_pages(tokens, &url, &[("time_range", time_range)], limit).await
+}
 
-    println!("Top tracks response status: {}", response.status());
+/// Fetches up to `limit` of the user's top artists, with their genres
+///
+/// **Rust Concept: Code Reuse Through Generics**
+/// The same `paging::fetch_pages` that fetches tracks fetches artists here;
+/// only the item type in the return type changes.
+async fn get_top_artists<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
+    time_range: &str,
+    limit: usize,
+) -> Result<TopArtistsResponse, MusicAnalysisError> {
+    let url = format!("{}/v1/me/top/artists", api_url);
+    paging::fetch_pages(tokens, &url, &[("time_range", time_range)], limit).await
+}
 
-    if response.status().is_success() {
-        let top_tracks: TopTracksResponse = response
-            .json()
-            .await
-            .map_err(|e| MusicAnalysisError::NetworkError(e.to_string()))?;
-        Ok(top_tracks)
-    } else {
-        Err(MusicAnalysisError::SpotifyAuth(format!(
-            "HTTP {}: {}",
-            response.status(),
-            response.status().as_str()
-        )))
+/// The `limit` genres shared by the most artists, most common first
+///
+/// **Rust Concept: HashMap and Sorting**
+/// `entry().or_insert()` counts each genre, then `sort_by` orders by count
+/// (descending) with ties broken alphabetically so the result is stable.
+fn top_genres(artists: &[Artist], limit: usize) -> Vec<&str> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for genre in artists.iter().flat_map(|artist| &artist.genres) {
+        *counts.entry(genre.as_str()).or_insert(0) += 1;
     }
+
+    let mut genres: Vec<(&str, usize)> = counts.into_iter().collect();
+    genres.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    genres
+        .into_iter()
+        .take(limit)
+        .map(|(genre, _)| genre)
+        .collect()
+}
+
+/// Scopes [`get_recently_played`] needs
+const RECENTLY_PLAYED_SCOPES: &[Scope] = &[Scope::UserReadRecentlyPlayed];
+
+/// Which part of the listening history to read
+///
+/// **Rust Concept: Enums with Data**
+/// Spotify accepts at most one of the `before` and `after` cursors, and
+/// an enum makes asking for both impossible.
+#[derive(Clone, Copy, Debug)]
+enum PlayedCursor {
+    /// The most recent plays
+    Latest,
+    /// Plays strictly before this time
+    Before(DateTime<Utc>),
+    /// Plays strictly after this time
+    #[allow(dead_code)] // `played_since` walks back from the latest instead
+    After(DateTime<Utc>),
 }
 
-/// Authenticates with Spotify and returns access token
+/// Fetches up to `limit` (at most 50) plays from the listening history,
+/// newest first
+async fn get_recently_played<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
+    cursor: PlayedCursor,
+    limit: usize,
+) -> Result<CursorPaging<PlayHistory>, MusicAnalysisError> {
+    let url = format!("{}/v1/me/player/recently-played", api_url);
+    let limit = limit.clamp(1, paging::MAX_PAGE_SIZE).to_string();
+    let cursor = match cursor {
+        PlayedCursor::Latest => None,
+        PlayedCursor::Before(time) => Some(("before", time.timestamp_millis().to_string())),
+        PlayedCursor::After(time) => Some(("after", time.timestamp_millis().to_string())),
+    };
+
+    let mut query = vec![("limit", limit.as_str())];
+    if let Some((name, millis)) = &cursor {
+        query.push((name, millis));
+    }
+    paging::fetch_json(tokens, &url, &query).await
+}
+
+/// Every play after `since`, newest first, e.g. everything since the last sync
+///
+/// **Rust Concept: Loops with State**
+/// A page holds at most 50 plays. Starting from the latest, when a full
+/// page still doesn't reach back to `since`, the loop asks for the plays
+/// `before` that page's oldest one, until it has covered the whole gap.
+async fn played_since<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
+    since: DateTime<Utc>,
+) -> Result<Vec<PlayHistory>, MusicAnalysisError> {
+    let mut plays = Vec::new();
+    let mut cursor = PlayedCursor::Latest;
+    loop {
+        let page = get_recently_played(api_url, tokens, cursor, paging::MAX_PAGE_SIZE).await?;
+        let full = page.items.len() == paging::MAX_PAGE_SIZE;
+        plays.extend(page.items.into_iter().filter(|play| play.played_at > since));
+
+        let oldest = page
+            .cursors
+            .and_then(|cursors| cursors.before)
+            .and_then(|millis| millis.parse().ok())
+            .and_then(DateTime::from_timestamp_millis);
+        match oldest {
+            Some(oldest) if full && oldest > since => cursor = PlayedCursor::Before(oldest),
+            _ => break,
+        }
+    }
+    Ok(plays)
+}
+
+/// Scopes [`liked_tracks`] needs
+const LIKED_TRACKS_SCOPES: &[Scope] = &[Scope::UserLibraryRead];
+
+/// Streams the user's Liked Songs, most recently added first
+///
+/// **Rust Concept: Streams**
+/// A `Stream` is the async counterpart of `TrackIterator`: each item is
+/// awaited, and the next page is only requested once the consumer has
+/// used up the current one. `map_ok` unwraps each `SavedTrack` while
+/// passing errors through unchanged.
+///
+/// **Rust Concept: Lifetimes in Return Types**
+/// `+ 'a` says the stream borrows `tokens` and can't outlive it.
+fn liked_tracks<'a, S: TokenSource>(
+    api_url: &str,
+    tokens: &'a TokenProvider<S>,
+) -> impl Stream<Item = Result<Track, MusicAnalysisError>> + 'a {
+    let url = format!("{}/v1/me/tracks", api_url);
+    paging::stream_items(tokens, url, Vec::new()).map_ok(|saved: SavedTrack| saved.track)
+}
+
+/// Fetches the page of up to `limit` Liked Songs starting at `offset`, each
+/// with the time it was liked
+///
+/// **Rust Concept: Reusing a Generic Helper**
+/// Like [`get_top_tracks`], this is one call to `paging::fetch_pages`, with
+/// `SavedTrack` as the item type this time.
+async fn get_saved_tracks<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
+    offset: u32,
+    limit: usize,
+) -> Result<Paging<SavedTrack>, MusicAnalysisError> {
+    let url = format!("{}/v1/me/tracks", api_url);
+    let offset = offset.to_string();
+    paging::fetch_pages(tokens, &url, &[("offset", &offset)], limit).await
+}
+
+/// Logs in with at least the `required` scopes and wraps the tokens in a
+/// provider that keeps them fresh
+///
+/// **Rust Concept: Generic Types**
+/// `TokenProvider<UserTokens>` is the generic provider specialised for user
+/// logins; API functions borrow it instead of a bare access token string.
+async fn spotify_tokens(
+    config: &SpotifyConfig,
+    required: &Scopes,
+) -> Result<TokenProvider<UserTokens>, MusicAnalysisError> {
+    let tokens = authenticate_spotify(config, required).await?;
+    let source = UserTokens::new(
+        &config.accounts_url,
+        &config.client_id,
+        config.client_secret.as_deref(),
+        config.token_cache.clone(),
+    );
+    Ok(TokenProvider::new(source, Some(tokens)))
+}
+
+/// Authenticates with Spotify and returns the tokens
 ///
 /// **Rust Concept: Reference Parameters**
 /// Takes `&SpotifyConfig` to borrow the config without taking ownership.
 /// This allows the caller to reuse the config after this function call.
-async fn authenticate_spotify(config: &SpotifyConfig) -> Result<String, MusicAnalysisError> {
-    println!("Getting authorization code...");
-    let auth_code = get_auth_code(&config.client_id, &config.redirect_uri, &config.scope)
-        .map_err(|e| MusicAnalysisError::SpotifyAuth(e.to_string()))?;
-    println!("Authorization code obtained successfully!");
+///
+/// **Rust Concept: Early Returns**
+/// Each cheaper option returns as soon as it succeeds:
+/// 1. A cached access token that hasn't expired yet
+/// 2. A refreshed token, if the cached one has expired
+/// 3. The interactive login, only if there is no cache, Spotify rejected
+///    the refresh token, or the cached login lacks a `required` scope
+async fn authenticate_spotify(
+    config: &SpotifyConfig,
+    required: &Scopes,
+) -> Result<AuthResponse, MusicAnalysisError> {
+    let cache = config.token_cache.as_ref();
+    let mut scopes = config.scopes.union(required);
 
-    let auth_response = auth::get_spotify_token(
-        &config.client_id,
-        &config.client_secret,
-        &config.redirect_uri,
-        &auth_code,
-    )
-    .await
-    .map_err(|e| MusicAnalysisError::SpotifyAuth(e.to_string()))?;
+    let cached = match cache {
+        Some(cache) => cache
+            .load()
+            .map_err(|e| MusicAnalysisError::SpotifyAuth(e.to_string()))?,
+        None => None,
+    };
+    if let Some(cached) = cached {
+        let missing = cached.scope.missing(&scopes);
+        if !missing.is_empty() {
+            println!(
+                "Your saved login doesn't include the scopes this command needs ({}); \
+                 logging in again with additional scopes.",
+                missing
+            );
+            // Keep the scopes granted before, so other commands go on working
+            scopes = scopes.union(&cached.scope);
+        } else if !cached.is_expired() {
+            println!("Using cached access token.");
+            return Ok(cached);
+        } else {
+            println!("Cached access token expired, refreshing...");
+            match auth::refresh_spotify_token(
+                &config.accounts_url,
+                &config.client_id,
+                config.client_secret.as_deref(),
+                &cached.refresh_token,
+            )
+            .await
+            {
+                Ok(mut refreshed) => {
+                    refreshed.inherit_scope(&cached);
+                    save_tokens(cache, &refreshed);
+                    return Ok(refreshed);
+                }
+                Err(AuthError::InvalidGrant(reason)) => {
+                    println!("Refresh token was rejected ({}), logging in again.", reason);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    let auth_response = interactive_login(config, &scopes).await?;
+    save_tokens(cache, &auth_response);
+    Ok(auth_response)
+}
+
+/// Runs the interactive authorization-code login (browser or headless)
+///
+/// **Rust Concept: Matching on Option**
+/// `match &config.client_secret` picks the confidential flow when a secret is
+/// configured and falls back to PKCE otherwise.
+async fn interactive_login(
+    config: &SpotifyConfig,
+    scopes: &Scopes,
+) -> Result<AuthResponse, MusicAnalysisError> {
+    let auth_response = match &config.client_secret {
+        Some(client_secret) => {
+            println!("Getting authorization code...");
+            let auth_code = get_auth_code(
+                &config.accounts_url,
+                &config.client_id,
+                &config.redirect_uri,
+                scopes,
+                config.login_mode,
+            )
+            .map_err(|e| MusicAnalysisError::SpotifyAuth(e.to_string()))?;
+            println!("Authorization code obtained successfully!");
+
+            auth::get_spotify_token(
+                &config.accounts_url,
+                &config.client_id,
+                client_secret,
+                &config.redirect_uri,
+                &auth_code,
+            )
+            .await
+        }
+        None => {
+            println!("Getting authorization code (PKCE)...");
+            let pkce = Pkce::generate();
+            let auth_code = get_auth_code_pkce(
+                &config.accounts_url,
+                &config.client_id,
+                &config.redirect_uri,
+                scopes,
+                &pkce,
+                config.login_mode,
+            )
+            .map_err(|e| MusicAnalysisError::SpotifyAuth(e.to_string()))?;
+            println!("Authorization code obtained successfully!");
+
+            auth::get_spotify_token_pkce(
+                &config.accounts_url,
+                &config.client_id,
+                &config.redirect_uri,
+                &auth_code,
+                &pkce.verifier,
+            )
+            .await
+        }
+    }?;
 
     println!("Access token obtained successfully!");
-    Ok(auth_response.access_token)
+    Ok(auth_response)
+}
+
+/// Saves tokens for the next run. A failed write only costs a login next time,
+/// so it is reported but not treated as an error.
+fn save_tokens(cache: Option<&TokenCache>, tokens: &AuthResponse) {
+    if let Some(cache) = cache {
+        if let Err(e) = cache.save(tokens) {
+            println!("Warning: could not cache tokens: {}", e);
+        }
+    }
 }
 
 /// # OpenAI Integration Functions
@@ -390,6 +1062,7 @@ async fn generate_ai_response(prompt: &str, model: &str) -> Result<String, Music
 /// 6. `join("\n")` - Combines into a single string
 async fn roast_or_toast_music_taste(
     top_tracks: &TopTracksResponse,
+    top_artists: &TopArtistsResponse,
     roast: bool,
     celebrity: &str,
     track_limit: usize,
@@ -404,13 +1077,15 @@ async fn roast_or_toast_music_taste(
         .map(|(i, track)| format!("{}. {}", i + 1, track.format()))
         .collect::<Vec<_>>()
         .join("\n");
+    let genres = top_genres(&top_artists.items, 5).join(", ");
 
     let action = if roast { "roast" } else { "toast" };
     let prompt = format!(
-        "Please write a one sentence {} of my music taste in the style of {}. Reference the track, genre, and/or artist in the list as part of the sentence. The sentence must be complete and under 50 characters. Do not use hashtags. Here are my top tracks:\n{}",
+        "Please write a one sentence {} of my music taste in the style of {}. Reference the track, genre, and/or artist in the list as part of the sentence. The sentence must be complete and under 50 characters. Do not use hashtags. Here are my top tracks:\n{}\nMy top genres are: {}",
         action,
         celebrity,
-        tracks_list
+        tracks_list,
+        genres
     );
 
     let response = generate_ai_response(&prompt, "gpt-3.5-turbo").await?;
@@ -455,46 +1130,585 @@ fn get_user_preferences() -> Result<(bool, String), MusicAnalysisError> {
 /// Main application logic with ownership patterns
 ///
 /// **Rust Concept: Ownership Flow**
-/// 1. `SpotifyConfig::from_env()` - Creates owned config
-/// 2. `authenticate_spotify(&config)` - Borrows config
-/// 3. `get_top_tracks()` - Uses borrowed access token
-/// 4. `roast_or_toast_music_taste()` - Borrows tracks data
+/// 1. `config: &SpotifyConfig` - Borrows the config the caller owns
+/// 2. `spotify_tokens(config)` - Borrows config, returns an owned token provider
+/// 3. `get_top_tracks()` and `get_top_artists()` - Borrow the token provider
+/// 4. `roast_or_toast_music_taste()` - Borrows tracks and artists data
 ///
 /// **Rust Concept: Error Propagation**
 /// Uses `?` operator throughout to propagate errors up to main function.
-async fn run_music_analysis() -> Result<(), MusicAnalysisError> {
-    let config = SpotifyConfig::from_env()?;
-
-    let access_token = authenticate_spotify(&config).await?;
+async fn run_music_analysis(config: &SpotifyConfig) -> Result<(), MusicAnalysisError> {
+    let tokens = spotify_tokens(config, &Scopes::new(TOP_TRACKS_SCOPES)).await?;
 
     println!("Fetching top tracks...");
-    let top_tracks = get_top_tracks(&access_token, "medium_term", 30).await?;
+    let top_tracks = get_top_tracks(&config.api_url, &tokens, "medium_term", 30).await?;
 
-    println!("Your top tracks:");
+    println!(
+        "Your top tracks ({} of {}):",
+        top_tracks.items.len(),
+        top_tracks.total
+    );
     println!("{}", top_tracks);
 
+    println!("Fetching top artists...");
+    let top_artists = get_top_artists(&config.api_url, &tokens, "medium_term", 10).await?;
+    println!("Your top artists:");
+    for (i, artist) in top_artists.items.iter().enumerate() {
+        println!("{}. {}", i + 1, artist.format());
+    }
+    println!();
+
     let (roast, celebrity) = get_user_preferences()?;
 
-    roast_or_toast_music_taste(&top_tracks, roast, &celebrity, 5).await?;
+    roast_or_toast_music_taste(&top_tracks, &top_artists, roast, &celebrity, 5).await?;
 
     Ok(())
 }
 
+/// Scopes [`create_top_tracks_playlist`] needs
+const PLAYLIST_SCOPES: &[Scope] = &[
+    Scope::UserTopRead,
+    Scope::PlaylistReadPrivate,
+    Scope::PlaylistModifyPrivate,
+];
+
+/// How many top tracks go into the playlist
+const PLAYLIST_TRACKS: usize = 50;
+
+/// Turns the user's top tracks into the private playlist `name`, replacing
+/// its contents if it already exists
+///
+/// **Rust Concept: Tuple Destructuring**
+/// `sync_playlist` returns `(Playlist, bool)`; `let (playlist, created)`
+/// unpacks both values in one step.
+async fn create_top_tracks_playlist(
+    config: &SpotifyConfig,
+    name: &str,
+) -> Result<(), MusicAnalysisError> {
+    let tokens = spotify_tokens(config, &Scopes::new(PLAYLIST_SCOPES)).await?;
+
+    println!("Fetching top tracks...");
+    let top_tracks =
+        get_top_tracks(&config.api_url, &tokens, "medium_term", PLAYLIST_TRACKS).await?;
+    let uris: Vec<String> = top_tracks
+        .items
+        .iter()
+        .map(|track| track.uri.clone())
+        .collect();
+
+    let description = format!("Your top {} tracks of the last six months", uris.len());
+    let (playlist, created) =
+        playlist::sync_playlist(&config.api_url, &tokens, name, &description, &uris).await?;
+
+    println!(
+        "{} {:?} with {} tracks: {}",
+        if created { "Created" } else { "Updated" },
+        playlist.name,
+        uris.len(),
+        playlist.external_urls.spotify
+    );
+    Ok(())
+}
+
+/// How far back `mood` looks
+const MOOD_WINDOW_HOURS: i64 = 24;
+
+/// Music Mood Analysis: asks the AI what the last day's listening says
+/// about the user's mood
+///
+/// **Rust Concept: Date and Time Arithmetic**
+/// `Utc::now() - chrono::Duration::hours(..)` gives a `DateTime<Utc>`;
+/// `with_timezone(&Local)` converts a play's time for display.
+///
+/// **Rust Concept: Recovering from Errors**
+/// Spotify no longer serves audio features to every app, so a failed
+/// lookup is reported and the analysis carries on with the play history
+/// alone instead of propagating the error with `?`.
+async fn analyze_mood(config: &SpotifyConfig) -> Result<(), MusicAnalysisError> {
+    let tokens = spotify_tokens(config, &Scopes::new(RECENTLY_PLAYED_SCOPES)).await?;
+
+    println!("Fetching recently played tracks...");
+    let since = Utc::now() - chrono::Duration::hours(MOOD_WINDOW_HOURS);
+    let mut plays = played_since(&config.api_url, &tokens, since).await?;
+    if plays.is_empty() {
+        println!(
+            "Nothing played in the last {} hours; using your latest plays instead.",
+            MOOD_WINDOW_HOURS
+        );
+        plays = get_recently_played(
+            &config.api_url,
+            &tokens,
+            PlayedCursor::Latest,
+            paging::MAX_PAGE_SIZE,
+        )
+        .await?
+        .items;
+    }
+    if plays.is_empty() {
+        println!("Spotify has no listening history for you yet.");
+        return Ok(());
+    }
+
+    let history = plays
+        .iter()
+        .map(|play| {
+            let from = play
+                .context
+                .as_ref()
+                .map(|context| format!(" (from a {})", context.kind))
+                .unwrap_or_default();
+            format!(
+                "{} {}{}",
+                play.played_at.with_timezone(&Local).format("%a %H:%M"),
+                play.track.format(),
+                from
+            )
+        })
+        .collect::<Vec<_>>();
+    println!("Your recent listening:\n{}\n", history.join("\n"));
+
+    let ids: Vec<String> = plays
+        .iter()
+        .filter_map(|play| play.track.id.clone())
+        .collect();
+    let sound = match audio_features::get_audio_features(&config.api_url, &tokens, &ids).await {
+        Ok(features) => {
+            let features: Vec<_> = features.into_iter().flatten().collect();
+            audio_features::summarize(&features)
+        }
+        Err(e) => {
+            println!("Warning: could not get audio features: {}", e);
+            None
+        }
+    };
+    if let Some(sound) = &sound {
+        println!("How it sounds: {}\n", sound);
+    }
+
+    initialize_openai()?;
+    let mut prompt = format!(
+        "Here is what I listened to recently, newest first, with the day and time of each play. In two or three sentences, describe the emotional patterns in these choices and what they suggest about my mood. Do not use hashtags.\n{}",
+        history.join("\n")
+    );
+    if let Some(sound) = sound {
+        prompt.push_str(&format!(
+            "\nSpotify's audio analysis of these tracks (0 to 1 scales; valence is how positive they sound): {}",
+            sound
+        ));
+    }
+    let response = generate_ai_response(&prompt, "gpt-3.5-turbo").await?;
+    println!("{}", response);
+
+    Ok(())
+}
+
+/// How many matches `find` prints
+const FIND_LIMIT: usize = 20;
+
+/// Prints the first Liked Songs whose title or artist contains `text`
+///
+/// **Rust Concept: Stream Adapters**
+/// `try_filter` and `take` work like their iterator namesakes. Because the
+/// stream is lazy, the search stops downloading the library as soon as
+/// `take` has enough matches.
+///
+/// **Rust Concept: Pinning**
+/// `Box::pin` fixes the stream in place in memory, which `try_next()` needs
+/// because the stream may hold references into itself across awaits.
+async fn find_liked_tracks(config: &SpotifyConfig, text: &str) -> Result<(), MusicAnalysisError> {
+    let tokens = spotify_tokens(config, &Scopes::new(LIKED_TRACKS_SCOPES)).await?;
+
+    let needle = text.to_lowercase();
+    let mut matches = Box::pin(
+        liked_tracks(&config.api_url, &tokens)
+            .try_filter(|track| future::ready(track.format().to_lowercase().contains(&needle)))
+            .take(FIND_LIMIT),
+    );
+
+    let mut found = 0;
+    while let Some(track) = matches.try_next().await? {
+        found += 1;
+        println!("{}. {}", found, track.format());
+    }
+    if found == 0 {
+        println!("None of your liked songs match {:?}.", text);
+    }
+    Ok(())
+}
+
+/// Prints a page of search results for each item type searched for
+async fn search_catalog(
+    config: &SpotifyConfig,
+    query: &SearchQuery,
+) -> Result<(), MusicAnalysisError> {
+    // Searching needs a login but no particular scope
+    let tokens = spotify_tokens(config, &Scopes::default()).await?;
+    let results = search::search(&config.api_url, &tokens, query).await?;
+
+    if let Some(tracks) = &results.tracks {
+        print_results("Tracks", tracks);
+    }
+    if let Some(artists) = &results.artists {
+        print_results("Artists", artists);
+    }
+    if let Some(albums) = &results.albums {
+        print_results("Albums", albums);
+    }
+    if let Some(playlists) = &results.playlists {
+        print_results("Playlists", playlists);
+    }
+    Ok(())
+}
+
+/// Prints `page` under `heading`, numbered from its offset
+///
+/// **Rust Concept: Trait Bounds**
+/// `T: Formattable` lets one function print pages of tracks, artists,
+/// albums and playlists: all it needs from an item is `format()`.
+fn print_results<T: Formattable>(heading: &str, page: &Paging<T>) {
+    if page.items.is_empty() {
+        println!("{}: no matches\n", heading);
+        return;
+    }
+    let first = page.offset as usize + 1;
+    println!(
+        "{} ({}-{} of {}):",
+        heading,
+        first,
+        first + page.items.len() - 1,
+        page.total
+    );
+    for (i, item) in page.items.iter().enumerate() {
+        println!("{}. {}", first + i, item.format());
+    }
+    println!();
+}
+
+/// Writes every Liked Song to `path`, a page at a time, resuming an
+/// interrupted export of the same file
+///
+/// **Rust Concept: Closures Capturing Variables**
+/// `export_error` captures `path`, so each `map_err` below can turn an
+/// `io::Error` into a `MusicAnalysisError` that says which file failed.
+async fn export_liked_tracks(config: &SpotifyConfig, path: &str) -> Result<(), MusicAnalysisError> {
+    let path = Path::new(path);
+    let format = ExportFormat::from_path(path).ok_or_else(|| {
+        MusicAnalysisError::UserInput(format!(
+            "Can't tell what format {} should be; name it .csv or .jsonl",
+            path.display()
+        ))
+    })?;
+    let export_error = |source: io::Error| MusicAnalysisError::Export {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let tokens = spotify_tokens(config, &Scopes::new(LIKED_TRACKS_SCOPES)).await?;
+    let mut export = Export::open(path, format).map_err(export_error)?;
+    if export.offset() > 0 {
+        // The last song written should still be where the export left it
+        let page = get_saved_tracks(&config.api_url, &tokens, export.offset() - 1, 1).await?;
+        let last = page.items.first();
+        if export
+            .restart_if_moved(
+                last.and_then(|saved| saved.track.id.as_deref()),
+                last.map(|saved| saved.added_at),
+            )
+            .map_err(export_error)?
+        {
+            println!("Your Liked Songs changed since the export started; starting over.");
+        } else {
+            println!("Resuming the export after {} songs.", export.offset());
+        }
+    }
+
+    loop {
+        let page = get_saved_tracks(
+            &config.api_url,
+            &tokens,
+            export.offset(),
+            paging::MAX_PAGE_SIZE,
+        )
+        .await?;
+        if export
+            .restart_if_changed(page.total)
+            .map_err(export_error)?
+        {
+            println!("Your Liked Songs changed since the export started; starting over.");
+            continue;
+        }
+
+        let records: Vec<ExportRecord> = page
+            .items
+            .iter()
+            .map(|saved| ExportRecord::new(saved.added_at, &saved.track))
+            .collect();
+        export.write_page(&records).map_err(export_error)?;
+        if let Some(last) = page.items.last() {
+            println!(
+                "{} of {}: {}",
+                export.offset(),
+                page.total,
+                last.track.format()
+            );
+        }
+        if page.next.is_none() || page.items.is_empty() {
+            break;
+        }
+    }
+
+    let exported = export.offset();
+    export.finish().map_err(export_error)?;
+    println!("Exported {} songs to {}.", exported, path.display());
+    Ok(())
+}
+
+// # Profile Commands
+//
+// **Rust Concept: Option Combinators**
+// `ok_or_else()` turns the `None` case into an error only when it happens.
+
+/// Opens the store holding the saved profiles
+fn profile_store() -> Result<ProfileStore, MusicAnalysisError> {
+    ProfileStore::default_location().ok_or_else(|| {
+        MusicAnalysisError::UserInput("No config directory to store profiles in".to_string())
+    })
+}
+
+/// Prints the names of all saved profiles
+fn list_profiles() -> Result<(), MusicAnalysisError> {
+    let names = profile_store()?
+        .list()
+        .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+    if names.is_empty() {
+        println!("No profiles yet. Create one with `profile add <name>`.");
+    }
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Prompts for a profile's settings and saves it, offering the current values
+/// as defaults when the profile already exists
+fn add_profile(name: &str) -> Result<(), MusicAnalysisError> {
+    let store = profile_store()?;
+    let existing = store.load(name).ok();
+
+    let mut client_id_input = Input::<String>::new();
+    client_id_input.with_prompt("Spotify client ID");
+    if let Some(client_id) = existing
+        .as_ref()
+        .map(|profile| profile.client_id.clone())
+        .or_else(|| env::var("SPOTIFY_CLIENT_ID").ok())
+    {
+        client_id_input.default(client_id);
+    }
+    let client_id = client_id_input
+        .interact_text()
+        .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+
+    let redirect_uri: String = Input::new()
+        .with_prompt("Redirect URI")
+        .default(
+            existing
+                .as_ref()
+                .map(|profile| profile.redirect_uri.clone())
+                .unwrap_or_else(|| DEFAULT_REDIRECT_URI.to_string()),
+        )
+        .interact_text()
+        .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+
+    let scopes: String = Input::new()
+        .with_prompt("Extra scopes to always request (space-separated)")
+        .default(
+            existing
+                .as_ref()
+                .map(|profile| profile.scopes.to_string())
+                .unwrap_or_default(),
+        )
+        .allow_empty(true)
+        .interact_text()
+        .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+
+    let profile = Profile {
+        client_id,
+        redirect_uri,
+        scopes: Scopes::from(scopes.as_str()),
+    };
+    store
+        .save(name, &profile)
+        .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+    println!(
+        "Saved profile {:?}. Use it with `--profile {}`.",
+        name, name
+    );
+    Ok(())
+}
+
+/// Deletes a profile together with its saved login
+fn remove_profile(name: &str) -> Result<(), MusicAnalysisError> {
+    let removed = profile_store()?
+        .remove(name)
+        .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+    if removed {
+        println!("Removed profile {:?}.", name);
+        Ok(())
+    } else {
+        Err(MusicAnalysisError::UserInput(format!(
+            "No profile named {:?}",
+            name
+        )))
+    }
+}
+
+/// The token cache of the selected profile (or the default one), without
+/// building a whole config: these commands don't need a passphrase to find it
+fn selected_token_cache(profile: Option<&str>) -> Result<Option<TokenCache>, MusicAnalysisError> {
+    match profile {
+        Some(name) => {
+            profile_store()?
+                .load(name)
+                .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+            Ok(profile::token_cache(name))
+        }
+        None => Ok(TokenCache::default_location()),
+    }
+}
+
+/// Deletes the saved login of the selected profile (or the default login)
+///
+/// **Rust Concept: Option Chaining**
+/// `cache` is an `Option`, so `.as_ref()` borrows what's inside without
+/// moving it out.
+fn logout(profile: Option<&str>) -> Result<(), MusicAnalysisError> {
+    let cache = selected_token_cache(profile)?;
+    let removed = match cache.as_ref() {
+        Some(cache) => {
+            auth::logout(cache).map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?
+        }
+        None => false,
+    };
+
+    if removed {
+        println!("Logged out; the saved tokens have been deleted.");
+    } else {
+        println!("No saved login to remove.");
+    }
+    println!("To revoke this app's access entirely, visit https://www.spotify.com/account/apps/");
+    Ok(())
+}
+
+/// Rewrites the saved tokens encrypted (`encrypt`) or as plain JSON
+///
+/// **Rust Concept: Let-Else**
+/// `let Some(x) = .. else { return .. };` binds `x` or leaves the function,
+/// keeping the happy path unindented.
+fn migrate_tokens(profile: Option<&str>, encrypt: bool) -> Result<(), MusicAnalysisError> {
+    let Some(cache) = selected_token_cache(profile)? else {
+        println!("No saved login to migrate.");
+        return Ok(());
+    };
+
+    // An encrypted store reads either format, so the same passphrase both
+    // opens the current file and, when encrypting, seals the new one
+    let encrypted = TokenStore::Encrypted {
+        passphrase: token_passphrase(encrypt)?,
+    };
+    let target = if encrypt {
+        encrypted.clone()
+    } else {
+        TokenStore::Plaintext
+    };
+    let migrated = cache
+        .with_store(encrypted)
+        .migrate(target)
+        .map_err(|e| MusicAnalysisError::UserInput(e.to_string()))?;
+
+    if !migrated {
+        println!("No saved login to migrate.");
+    } else if encrypt {
+        println!(
+            "The saved tokens are now encrypted. Set SPOTIFY_TOKEN_STORE=encrypted to use them."
+        );
+    } else {
+        println!("The saved tokens are now stored as plain JSON. Unset SPOTIFY_TOKEN_STORE to keep them that way.");
+    }
+    Ok(())
+}
+
+/// The config of the profile picked with `--profile`, or the environment's
+fn selected_config(profile: Option<&str>) -> Result<SpotifyConfig, MusicAnalysisError> {
+    match profile {
+        Some(name) => SpotifyConfig::from_profile(name),
+        None => SpotifyConfig::from_env(),
+    }
+}
+
+/// Runs the command picked on the command line
+///
+/// **Rust Concept: Exhaustive Matching**
+/// The `match` must handle every `Command` variant, so adding a command
+/// without wiring it up here is a compile error.
+async fn run(args: Args) -> Result<(), MusicAnalysisError> {
+    match args.command {
+        Command::Analyze => run_music_analysis(&selected_config(args.profile.as_deref())?).await,
+        Command::Find(text) => {
+            find_liked_tracks(&selected_config(args.profile.as_deref())?, &text).await
+        }
+        Command::Search(query) => {
+            search_catalog(&selected_config(args.profile.as_deref())?, &query).await
+        }
+        Command::Export(path) => {
+            export_liked_tracks(&selected_config(args.profile.as_deref())?, &path).await
+        }
+        Command::Playlist(name) => {
+            create_top_tracks_playlist(&selected_config(args.profile.as_deref())?, &name).await
+        }
+        Command::Mood => analyze_mood(&selected_config(args.profile.as_deref())?).await,
+        Command::Logout => logout(args.profile.as_deref()),
+        Command::TokensEncrypt => migrate_tokens(args.profile.as_deref(), true),
+        Command::TokensDecrypt => migrate_tokens(args.profile.as_deref(), false),
+        Command::ProfileList => list_profiles(),
+        Command::ProfileAdd(name) => add_profile(&name),
+        Command::ProfileRemove(name) => remove_profile(&name),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    }
+}
+
 /// # Application Entry Point
 ///
-/// **Rust Concept: Error Type Conversion**
-/// Converts our custom error type to the generic error type expected by main.
-/// This allows our custom error handling while maintaining compatibility.
+/// **Rust Concept: Error Reporting**
+/// Returning `Err` from `main` would print the error's `Debug` form. Printing
+/// its `Display` form instead shows the advice each variant carries, and
+/// following `source()` shows what caused it.
 
 /// Main function with proper error handling
 ///
-/// **Rust Concept: Error Type Conversion**
-/// `map_err(|e| Box::new(e) as Box<dyn std::error::Error>)` converts our custom
-/// error type to the generic error type that main expects.
+/// **Rust Concept: Exit Codes**
+/// `ExitCode::FAILURE` tells the shell the command failed, just as returning
+/// an `Err` would have.
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
     dotenv().ok();
-    run_music_analysis()
-        .await
-        .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)
+    let result = match cli::parse_args(env::args().skip(1)) {
+        Ok(args) => run(args).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            let mut source = e.source();
+            while let Some(cause) = source {
+                eprintln!("  caused by: {}", cause);
+                source = cause.source();
+            }
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,149 @@
+//! The Spotify catalog objects every feature deserializes: tracks, their
+//! albums and artists, and the images and links that hang off them.
+//!
+//! Spotify returns several shapes of the same object: a "simplified" artist
+//! inside a track, a full one from the top artists endpoint, a track without
+//! an album when listing an album's tracks, and local files with `null` ids,
+//! popularity and links. Rather than a struct per shape, each object has one
+//! struct whose fields fall back to empty/zero/`None` when Spotify leaves
+//! them out or sends `null`.
+
+// The CLI prints only a few of these fields; the rest are here so features
+// don't need their own partial structs
+#![allow(dead_code)]
+
+use serde::{Deserialize, Deserializer};
+
+/// Represents a single track from Spotify
+///
+/// **Rust Concept: Derive Macros**
+/// - `#[derive(Deserialize, Debug, Clone)]` automatically implements:
+///   - `Deserialize`: Converts JSON to this struct
+///   - `Debug`: Allows printing with `{:?}`
+///   - `Clone`: Allows creating copies of the struct
+///
+/// **Rust Concept: Field Attributes**
+/// `#[serde(default, deserialize_with = "null_as_default")]` makes a field
+/// optional twice over: `default` covers a missing key, and the custom
+/// deserializer turns an explicit `null` into the same default value.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Track {
+    /// `None` for local files, which Spotify has no catalog entry for
+    pub id: Option<String>,
+    pub name: String,
+    /// `spotify:track:<id>`, used to add the track to playlists
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub uri: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub artists: Vec<Artist>,
+    /// `None` where the track is listed as part of its album
+    #[serde(default)]
+    pub album: Option<Album>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub duration_ms: u64,
+    /// 0 to 100, based on how much the track has been played recently
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub popularity: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub explicit: bool,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub external_ids: ExternalIds,
+    /// A 30 second MP3 clip; Spotify no longer provides one for most tracks
+    #[serde(default)]
+    pub preview_url: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub external_urls: ExternalUrls,
+    /// Which disc of the album the track is on, usually 1
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub disc_number: u32,
+    /// Position on its disc, starting at 1
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub track_number: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub is_local: bool,
+}
+
+/// The album a track appears on
+#[derive(Deserialize, Debug, Clone)]
+pub struct Album {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub artists: Vec<Artist>,
+    /// `1997`, `1997-05` or `1997-05-21`, depending on
+    /// `release_date_precision`
+    #[serde(default)]
+    pub release_date: Option<String>,
+    /// `year`, `month` or `day`
+    #[serde(default)]
+    pub release_date_precision: Option<String>,
+    /// Cover art, widest first
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub images: Vec<Image>,
+}
+
+/// Represents an artist from Spotify
+///
+/// **Rust Concept: Owned vs Borrowed Data**
+/// - `String` is an owned type (we own the memory)
+/// - `&str` would be a borrowed reference (we don't own it)
+///
+/// **Rust Concept: Serde Defaults**
+/// The artists inside a track are "simplified" and only carry the id and
+/// name. `#[serde(default)]` lets the same struct parse them by filling in
+/// empty genres, zero popularity and so on; the top artists endpoint returns
+/// the full object.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Artist {
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub id: String,
+    pub name: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub genres: Vec<String>,
+    /// 0 to 100, based on how much the artist's tracks are played
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub popularity: u32,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub followers: Followers,
+    /// Artist pictures, widest first
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub images: Vec<Image>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Followers {
+    pub total: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Image {
+    pub url: String,
+    pub height: Option<u32>,
+    pub width: Option<u32>,
+}
+
+/// Identifiers of the track outside Spotify
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ExternalIds {
+    /// International Standard Recording Code
+    pub isrc: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ExternalUrls {
+    /// The object's page on open.spotify.com; empty for local files
+    #[serde(default)]
+    pub spotify: String,
+}
+
+/// Deserializes `null` as `T::default()`, for fields Spotify sometimes sends
+/// as `null` instead of leaving out.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
@@ -0,0 +1,164 @@
+//! Spotify's paging objects, shared by every endpoint that returns a list, and
+//! helpers that follow `next` links: [`fetch_pages`] up to a count, and
+//! [`stream_items`] for as long as the consumer keeps pulling.
+//!
+//! Most lists page by offset ([`Paging`]); a few, like recently played, page
+//! by timestamp cursors instead ([`CursorPaging`]).
+
+use crate::auth::provider::{TokenProvider, TokenSource};
+use crate::http;
+use crate::MusicAnalysisError;
+use async_stream::try_stream;
+use futures::Stream;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use url::Url;
+
+/// The most items Spotify returns in a single page.
+pub const MAX_PAGE_SIZE: usize = 50;
+
+#[derive(Deserialize, Debug)]
+pub struct Paging<T> {
+    pub items: Vec<T>,
+    /// URL of the following page, `None` on the last one
+    pub next: Option<String>,
+    #[serde(default)]
+    pub offset: u32,
+    /// Items available in total, across all pages
+    #[serde(default)]
+    pub total: u32,
+}
+
+/// A page of a list that is navigated by cursors rather than offsets.
+#[derive(Deserialize, Debug)]
+pub struct CursorPaging<T> {
+    pub items: Vec<T>,
+    /// `None` when the page is empty
+    pub cursors: Option<Cursors>,
+}
+
+/// Where a [`CursorPaging`] page ends. Spotify also sends an `after` cursor,
+/// but reading forward is done with a timestamp the caller already has.
+#[derive(Deserialize, Debug)]
+pub struct Cursors {
+    /// Unix time in milliseconds, as a string; pass it as `before` to get
+    /// the items older than this page
+    pub before: Option<String>,
+}
+
+/// Fetches up to `count` items from the list endpoint at `url`, one page of at
+/// most [`MAX_PAGE_SIZE`] at a time. `query` is sent with the first request
+/// only; Spotify's `next` links already carry it.
+///
+/// The result holds every item fetched, with the `offset` and `total` of the
+/// first page and the `next` link of the last.
+pub async fn fetch_pages<T, S>(
+    tokens: &TokenProvider<S>,
+    url: &str,
+    query: &[(&str, &str)],
+    count: usize,
+) -> Result<Paging<T>, MusicAnalysisError>
+where
+    T: DeserializeOwned,
+    S: TokenSource,
+{
+    let limit = count.clamp(1, MAX_PAGE_SIZE).to_string();
+    let mut query = query.to_vec();
+    query.push(("limit", &limit));
+
+    let mut paging: Paging<T> = fetch_json(tokens, url, &query).await?;
+    while paging.items.len() < count {
+        let Some(next) = paging.next.take() else {
+            break;
+        };
+        // Ask for no more than is still wanted, so none of the page is dropped
+        // and the `next` link handed back starts right after the last item
+        let next = with_limit(&next, count - paging.items.len());
+        let mut page: Paging<T> = fetch_json(tokens, &next, &[]).await?;
+        paging.items.append(&mut page.items);
+        paging.next = page.next;
+    }
+    if paging.items.len() > count {
+        // `next` would skip the items dropped here
+        paging.items.truncate(count);
+        paging.next = None;
+    }
+    Ok(paging)
+}
+
+/// Replaces the `limit` of a `next` link with `limit`, capped at
+/// [`MAX_PAGE_SIZE`]. A link that doesn't parse is returned unchanged.
+fn with_limit(next: &str, limit: usize) -> String {
+    let Ok(mut url) = Url::parse(next) else {
+        return next.to_string();
+    };
+    let limit = limit.clamp(1, MAX_PAGE_SIZE).to_string();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "limit")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("limit", &limit);
+    url.into()
+}
+
+/// Streams the items of the list endpoint at `url`, fetching a page only once
+/// the consumer has pulled every item of the previous one. Stopping early
+/// (e.g. with `take`) skips the remaining requests.
+pub fn stream_items<'a, T, S>(
+    tokens: &'a TokenProvider<S>,
+    url: String,
+    query: Vec<(&'a str, String)>,
+) -> impl Stream<Item = Result<T, MusicAnalysisError>> + 'a
+where
+    T: DeserializeOwned + 'a,
+    S: TokenSource + 'a,
+{
+    try_stream! {
+        let limit = MAX_PAGE_SIZE.to_string();
+        let mut first_query: Vec<(&str, &str)> = query.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        first_query.push(("limit", &limit));
+
+        let mut page: Paging<T> = fetch_json(tokens, &url, &first_query).await?;
+        loop {
+            for item in page.items {
+                yield item;
+            }
+            let Some(next) = page.next else {
+                break;
+            };
+            page = fetch_json(tokens, &next, &[]).await?;
+        }
+    }
+}
+
+/// GETs `url` and decodes the JSON body, e.g. a single page.
+pub async fn fetch_json<T, S>(
+    tokens: &TokenProvider<S>,
+    url: &str,
+    query: &[(&str, &str)],
+) -> Result<T, MusicAnalysisError>
+where
+    T: DeserializeOwned,
+    S: TokenSource,
+{
+    let response = tokens
+        .send(|access_token| {
+            http::client()
+                .get(url)
+                .header(AUTHORIZATION, format!("Bearer {}", access_token))
+                .header(CONTENT_TYPE, "application/json")
+                .query(query)
+        })
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(MusicAnalysisError::from_response(response).await);
+    }
+    let body = response.text().await.map_err(MusicAnalysisError::Request)?;
+    crate::decode_json(&body)
+}
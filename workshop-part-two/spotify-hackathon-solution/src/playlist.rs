@@ -0,0 +1,123 @@
+//! Creating and filling playlists for the current user. Playlists are matched
+//! by name, so running the same command again updates its playlist instead of
+//! creating a duplicate.
+
+use crate::auth::provider::{TokenProvider, TokenSource};
+use crate::http;
+use crate::model::ExternalUrls;
+use crate::paging;
+use crate::MusicAnalysisError;
+use futures::TryStreamExt;
+use reqwest::header::AUTHORIZATION;
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// The most tracks Spotify accepts in one add or replace request.
+pub const MAX_TRACKS_PER_REQUEST: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct User {
+    pub id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Playlist {
+    pub id: String,
+    pub name: String,
+    pub owner: User,
+    pub external_urls: ExternalUrls,
+}
+
+/// Makes the current user's private playlist `name` hold exactly `uris`, in
+/// order: an existing playlist of that name has its description and tracks
+/// replaced, otherwise a new one is created. Returns the playlist and whether
+/// it was created.
+pub async fn sync_playlist<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
+    name: &str,
+    description: &str,
+    uris: &[String],
+) -> Result<(Playlist, bool), MusicAnalysisError> {
+    let me_url = format!("{}/v1/me", api_url);
+    let user: User = paging::fetch_json(tokens, &me_url, &[]).await?;
+
+    let mut batches = uris.chunks(MAX_TRACKS_PER_REQUEST);
+    let (playlist, created) = match find_playlist(api_url, tokens, &user, name).await? {
+        Some(playlist) => {
+            let url = format!("{}/v1/playlists/{}", api_url, playlist.id);
+            send_json(
+                tokens,
+                Method::PUT,
+                &url,
+                &json!({ "description": description }),
+            )
+            .await?;
+            // Replacing with the first batch (or nothing) clears the old tracks
+            let first = batches.next().unwrap_or_default();
+            send_json(
+                tokens,
+                Method::PUT,
+                &format!("{}/tracks", url),
+                &json!({ "uris": first }),
+            )
+            .await?;
+            (playlist, false)
+        }
+        None => {
+            let url = format!("{}/v1/users/{}/playlists", api_url, user.id);
+            let body = json!({ "name": name, "description": description, "public": false });
+            let response = send_json(tokens, Method::POST, &url, &body).await?;
+            (crate::decode_json(&response)?, true)
+        }
+    };
+
+    let tracks_url = format!("{}/v1/playlists/{}/tracks", api_url, playlist.id);
+    for batch in batches {
+        send_json(tokens, Method::POST, &tracks_url, &json!({ "uris": batch })).await?;
+    }
+    Ok((playlist, created))
+}
+
+/// The first of `user`'s own playlists called `name`. Playlists they only
+/// follow are skipped, since they can't be edited.
+async fn find_playlist<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
+    user: &User,
+    name: &str,
+) -> Result<Option<Playlist>, MusicAnalysisError> {
+    let url = format!("{}/v1/me/playlists", api_url);
+    let mut playlists = Box::pin(paging::stream_items::<Playlist, S>(tokens, url, Vec::new()));
+    while let Some(playlist) = playlists.try_next().await? {
+        if playlist.name == name && playlist.owner.id == user.id {
+            return Ok(Some(playlist));
+        }
+    }
+    Ok(None)
+}
+
+/// Sends `body` as JSON and returns the response body.
+async fn send_json<S: TokenSource>(
+    tokens: &TokenProvider<S>,
+    method: Method,
+    url: &str,
+    body: &Value,
+) -> Result<String, MusicAnalysisError> {
+    let response = tokens
+        .send(|access_token| {
+            http::client()
+                .request(method.clone(), url)
+                .header(AUTHORIZATION, format!("Bearer {}", access_token))
+                .json(body)
+        })
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(MusicAnalysisError::from_response(response).await);
+    }
+    response.text().await.map_err(MusicAnalysisError::Request)
+}
@@ -0,0 +1,119 @@
+//! Named account profiles, so several people can share one machine without
+//! logging each other out. Each profile has its own login settings, stored in
+//! the user's config directory, and its own token cache.
+
+use crate::auth::cache::TokenCache;
+use crate::auth::scope::Scopes;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// The settings a profile logs in with. The client secret is deliberately not
+/// stored: it comes from `SPOTIFY_CLIENT_SECRET` when `SPOTIFY_CLIENT_ID` is
+/// the same app, and otherwise the profile logs in with PKCE.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Profile {
+    pub client_id: String,
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub scopes: Scopes,
+}
+
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    /// `<user config dir>/spotify-hackathon/profiles`, e.g.
+    /// `~/.config/spotify-hackathon/profiles` on Linux.
+    pub fn default_location() -> Option<Self> {
+        let dir = dirs::config_dir()?
+            .join("spotify-hackathon")
+            .join("profiles");
+        Some(ProfileStore { dir })
+    }
+
+    /// Names of all saved profiles, sorted.
+    pub fn list(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn load(&self, name: &str) -> Result<Profile, Box<dyn Error>> {
+        let contents = fs::read_to_string(self.path(name)?).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => format!("No profile named {:?}", name).into(),
+            _ => Box::new(e) as Box<dyn Error>,
+        })?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, name: &str, profile: &Profile) -> Result<(), Box<dyn Error>> {
+        let path = self.path(name)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::write(path, serde_json::to_string_pretty(profile)?)?;
+        Ok(())
+    }
+
+    /// Deletes the profile and its cached tokens. Returns `false` if there was
+    /// no such profile.
+    pub fn remove(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        let path = self.path(name)?;
+        if let Some(cache) = token_cache(name) {
+            cache.clear()?;
+        }
+        match fs::remove_file(path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        validate_name(name)?;
+        Ok(self.dir.join(format!("{}.json", name)))
+    }
+}
+
+/// The token cache for profile `name`:
+/// `<user cache dir>/spotify-hackathon/profiles/<name>/token.json`
+pub fn token_cache(name: &str) -> Option<TokenCache> {
+    let dir = dirs::cache_dir()?
+        .join("spotify-hackathon")
+        .join("profiles")
+        .join(name);
+    Some(TokenCache::new(dir.join("token.json")))
+}
+
+/// Profile names become file names, so keep them to a safe character set.
+fn validate_name(name: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid profile name {:?}: use letters, digits, '-' and '_'",
+            name
+        )
+        .into())
+    }
+}
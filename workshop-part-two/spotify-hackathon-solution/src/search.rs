@@ -0,0 +1,221 @@
+//! Searching Spotify's catalog. [`SearchQuery`] builds the `q` parameter from
+//! free text and field filters (`artist:`, `year:1990-1999`, `genre:`, ...)
+//! so callers don't have to know Spotify's query syntax, and [`search`]
+//! returns a page of typed results for each item type asked for.
+
+use crate::auth::provider::{TokenProvider, TokenSource};
+use crate::model::{Album, Artist, Track};
+use crate::paging::{self, Paging, MAX_PAGE_SIZE};
+use crate::playlist::Playlist;
+use crate::MusicAnalysisError;
+use serde::Deserialize;
+use std::ops::RangeInclusive;
+
+/// The highest `offset` Spotify allows in a search.
+pub const MAX_OFFSET: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchType {
+    Track,
+    Artist,
+    Album,
+    Playlist,
+}
+
+impl SearchType {
+    pub const ALL: [SearchType; 4] = [
+        SearchType::Track,
+        SearchType::Artist,
+        SearchType::Album,
+        SearchType::Playlist,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchType::Track => "track",
+            SearchType::Artist => "artist",
+            SearchType::Album => "album",
+            SearchType::Playlist => "playlist",
+        }
+    }
+}
+
+/// A search, built up one filter at a time:
+///
+/// ```ignore
+/// let query = SearchQuery::new("karma")
+///     .artist("Radiohead")
+///     .years(1990..=1999)
+///     .types(&[SearchType::Track])
+///     .market("GB");
+/// ```
+///
+/// Searches all item types, 10 results each, until told otherwise.
+#[derive(Clone, Debug)]
+pub struct SearchQuery {
+    terms: Vec<String>,
+    types: Vec<SearchType>,
+    market: Option<String>,
+    limit: usize,
+    offset: usize,
+}
+
+impl SearchQuery {
+    pub fn new(text: &str) -> Self {
+        SearchQuery {
+            terms: Vec::new(),
+            types: SearchType::ALL.to_vec(),
+            market: None,
+            limit: 10,
+            offset: 0,
+        }
+        .text(text)
+    }
+
+    /// Adds free text, matched against names and descriptions.
+    pub fn text(mut self, text: &str) -> Self {
+        let text = text.trim();
+        if !text.is_empty() {
+            self.terms.push(text.to_string());
+        }
+        self
+    }
+
+    pub fn artist(self, name: &str) -> Self {
+        self.filter("artist", name)
+    }
+
+    pub fn album(self, name: &str) -> Self {
+        self.filter("album", name)
+    }
+
+    pub fn track(self, name: &str) -> Self {
+        self.filter("track", name)
+    }
+
+    /// Only artists with this genre, and tracks by them.
+    pub fn genre(self, genre: &str) -> Self {
+        self.filter("genre", genre)
+    }
+
+    pub fn isrc(self, isrc: &str) -> Self {
+        self.filter("isrc", isrc)
+    }
+
+    /// Only items released in `year`.
+    pub fn year(self, year: u16) -> Self {
+        self.filter("year", &year.to_string())
+    }
+
+    /// Only items released in `years`, e.g. `1990..=1999`.
+    pub fn years(self, years: RangeInclusive<u16>) -> Self {
+        self.filter("year", &format!("{}-{}", years.start(), years.end()))
+    }
+
+    /// Which item types to search for. Leaving it empty searches all of them.
+    pub fn types(mut self, types: &[SearchType]) -> Self {
+        self.types = if types.is_empty() {
+            SearchType::ALL.to_vec()
+        } else {
+            types.to_vec()
+        };
+        self
+    }
+
+    /// Only items available in this ISO 3166-1 alpha-2 country, e.g. `GB`.
+    pub fn market(mut self, market: &str) -> Self {
+        self.market = Some(market.to_string());
+        self
+    }
+
+    /// Results per item type, at most [`MAX_PAGE_SIZE`].
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit.clamp(1, MAX_PAGE_SIZE);
+        self
+    }
+
+    /// How many results of each type to skip, at most [`MAX_OFFSET`].
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset.min(MAX_OFFSET);
+        self
+    }
+
+    /// The `q` parameter, e.g. `karma artist:Radiohead year:1990-1999`.
+    pub fn q(&self) -> String {
+        self.terms.join(" ")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Values with spaces are quoted, so `artist:"Daft Punk"` doesn't also
+    /// search for "Punk" anywhere.
+    fn filter(mut self, field: &str, value: &str) -> Self {
+        let value = value.trim().replace('"', "");
+        if value.contains(char::is_whitespace) {
+            self.terms.push(format!("{}:\"{}\"", field, value));
+        } else if !value.is_empty() {
+            self.terms.push(format!("{}:{}", field, value));
+        }
+        self
+    }
+}
+
+/// One page of results for each type searched for; types that weren't
+/// searched for are `None`.
+#[derive(Debug)]
+pub struct SearchResults {
+    pub tracks: Option<Paging<Track>>,
+    pub artists: Option<Paging<Artist>>,
+    pub albums: Option<Paging<Album>>,
+    pub playlists: Option<Paging<Playlist>>,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    tracks: Option<Paging<Track>>,
+    artists: Option<Paging<Artist>>,
+    albums: Option<Paging<Album>>,
+    /// Spotify sends `null` in place of playlists it can't show
+    playlists: Option<Paging<Option<Playlist>>>,
+}
+
+pub async fn search<S: TokenSource>(
+    api_url: &str,
+    tokens: &TokenProvider<S>,
+    query: &SearchQuery,
+) -> Result<SearchResults, MusicAnalysisError> {
+    let url = format!("{}/v1/search", api_url);
+    let q = query.q();
+    let types = query
+        .types
+        .iter()
+        .map(SearchType::as_str)
+        .collect::<Vec<_>>()
+        .join(",");
+    let limit = query.limit.to_string();
+    let offset = query.offset.to_string();
+    let mut params = vec![
+        ("q", q.as_str()),
+        ("type", types.as_str()),
+        ("limit", limit.as_str()),
+        ("offset", offset.as_str()),
+    ];
+    if let Some(market) = &query.market {
+        params.push(("market", market));
+    }
+
+    let response: SearchResponse = paging::fetch_json(tokens, &url, &params).await?;
+    Ok(SearchResults {
+        tracks: response.tracks,
+        artists: response.artists,
+        albums: response.albums,
+        playlists: response.playlists.map(|page| Paging {
+            items: page.items.into_iter().flatten().collect(),
+            next: page.next,
+            offset: page.offset,
+            total: page.total,
+        }),
+    })
+}